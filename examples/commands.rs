@@ -17,7 +17,7 @@ use cgmath::Rad;
 use aphid::{HashSet, Seconds};
 
 use jam::color;
-use jam::{Vec3, Vec2, JamResult, Dimensions, Color, rgb, Camera, InputState, FontDirectory};
+use jam::{Vec3, Vec2, JamResult, Dimensions, Color, rgb, Camera, InputState, FontDirectory, App, AppState, AppConfig};
 
 use jam::render::*;
 use jam::render::gfx::{Renderer, GeometryBuffer, OpenGLRenderer, construct_opengl_renderer};
@@ -31,17 +31,13 @@ fn main() {
     let texture_dir = TextureDirectory::for_path("resources/textures", hashset!["png".into()]);
     let font_dir = FontDirectory::for_path("resources/fonts");
 
-    let file_resources = FileResources {
-        resources: resources_path,
-        shader_pair : shader_pair,
-        texture_directory: texture_dir,
-        font_directory: font_dir,
-    };
+    let file_resources = FileResources::new(resources_path, shader_pair, texture_dir, font_dir)
+        .with_compact_shader_pair(ShaderPair::for_paths("resources/shader/compact.vert", "resources/shader/fat.frag"));
 
     println!("creating renderer");
     let renderer = construct_opengl_renderer(file_resources, (800, 600), true, "commands example".into()).expect("a renderer");
     println!("done creating renderer");
-    let mut app = App {
+    let state = CommandsExample {
         name: "mixalot".into(),
         camera: Camera {
             at: Vec3::new(0.0, 0.0, 0.0),
@@ -55,45 +51,49 @@ fn main() {
         zoom: 1.0,
         points_per_unit: 16.0,
         n: 0, // frame counter
-        renderer: renderer,
         geometry: HashMap::default(),
     };
-    app.run();
+    let mut app = App::new(state, renderer, AppConfig::default()).expect("a valid config");
+    app.run().expect("a clean exit");
 }
 
-struct App {
+struct CommandsExample {
     name : String,
     camera : Camera,
     zoom : f64,
     points_per_unit : f64,
     n : u64,
-    renderer: OpenGLRenderer,
     geometry : HashMap<String, GeometryBuffer<gfx_device_gl::Resources>>,
 }
 
-impl App {
-    fn run(&mut self) {
-        let mut last_time = time::precise_time_ns();
-        'main: loop {
-            let (dimensions, input_state) = self.renderer.begin_frame(rgb(132, 193, 255));
-
-            if input_state.close {
-                break;
-            }
+impl AppState for CommandsExample {
+    fn input(&mut self, input_state:&InputState, dimensions:Dimensions) {
+        use glutin::VirtualKeyCode;
 
-//            println!("dimensions -> {:?}", dimensions);
-            let time = time::precise_time_ns();
-            let delta_time = ((time - last_time) as f64) / 1_000_000.0;
+        self.camera.viewport = dimensions;
 
-            self.update(&input_state, dimensions, delta_time);  
+        if input_state.keys.pushed.contains(&VirtualKeyCode::P) {
+//            println!("take a screenshot!");
+//            let image = self.renderer.screenshot();
+//            let mut output = std::fs::file::create(&path::new("screenshot.png")).unwrap();
+//            image.save(&mut output, image::imageformat::png).unwrap();
+        }
+    }
 
-            let res = self.render().expect("successful rendering");
+    fn update(&mut self, _dt: Seconds) {
+        self.n += 1;
 
-            last_time = time;
+        self.camera.at = Vec3::new(17.0, 0.0, 17.0);
+        // self.camera.at = Vec3::new(8.0, 0.0, 8.0);
+        self.camera.points_per_unit = self.points_per_unit * self.zoom;
+    }
 
-        }
+    fn render(&mut self, renderer: &mut OpenGLRenderer, _alpha: f64) -> JamResult<()> {
+        self.render_zones(renderer)
     }
+}
 
+impl CommandsExample {
     fn units_per_point(&self) -> f64 {
         1.0 / self.points_per_unit
     }
@@ -104,29 +104,7 @@ impl App {
         GeometryTesselator::new(tesselator_scale)
     }
 
-    fn update(&mut self, input_state:&InputState, dimensions:Dimensions, delta_time: Seconds) {
-        use glutin::VirtualKeyCode;
-        self.n += 1;
-
-        self.camera.at = Vec3::new(17.0, 0.0, 17.0);
-        // self.camera.at = Vec3::new(8.0, 0.0, 8.0);
-        self.camera.points_per_unit = self.points_per_unit * self.zoom;
-        self.camera.viewport = dimensions;
-
-//        println!("Camera viewpoinrt -> {:?}", self.camera.viewport);
-
-        // let (mx, my) = input_state.mouse.at;
-        // let mouse_at = self.camera.ui_line_segment_for_mouse_position(mx, my);
-
-        if input_state.keys.pushed.contains(&VirtualKeyCode::P) {
-//            println!("take a screenshot!");
-//            let image = self.renderer.screenshot();
-//            let mut output = std::fs::file::create(&path::new("screenshot.png")).unwrap();
-//            image.save(&mut output, image::imageformat::png).unwrap();
-        }
-    }
-
-    fn render(&mut self) -> JamResult<()> {
+    fn render_zones(&mut self, renderer: &mut OpenGLRenderer) -> JamResult<()> {
 //        use jam::font::FontDescription;
         
 //        let font_description = FontDescription { family: "Roboto-Medium".into(), pixel_size: (32f64 * self.camera.viewport.scale()) as u32 };
@@ -182,14 +160,14 @@ impl App {
 
             if (an % 16) == i && on_second {
                 raster(&mut t, &mut vertices, raster_color, (xo * 9) as f64, (zo * 9) as f64);
-                let geo = self.renderer.draw_vertices(&vertices, Uniforms {
+                let geo = renderer.draw_vertices(&vertices, Uniforms {
                     transform : down_size_m4(camera.view_projection().into()),
                     color: color::WHITE,
                 }, Blend::None)?;
                 cache.insert(name, geo);
             } else if ((an+8) % 16) == i && on_second {
                 raster(&mut t, &mut vertices, raster_color, (xo * 9) as f64, (zo * 9) as f64);
-                cache.insert(name, self.renderer.upload(&vertices));
+                cache.insert(name, renderer.upload(&vertices));
             } else {
                 let rem = (xo + zo) % 3;
                 let color = match rem {
@@ -203,7 +181,7 @@ impl App {
                         1 => Blend::Add,
                         _ => Blend::None,
                     };
-                    self.renderer.draw(geo, Uniforms {
+                    renderer.draw(geo, Uniforms {
                         transform: down_size_m4(self.camera.view_projection().into()),
                         color: color,
                     },blend)?;
@@ -223,7 +201,8 @@ impl App {
 //                u_max: 128,
 //                v_min: 0,
 //                v_max: 128,
-//                texture_size: 1024,
+//                texture_width: 1024,
+//                texture_height: 1024,
 //            };
 //            t.color = color::WHITE.float_raw();
 //            t.draw_ui(&mut vertices, &texture_region, 0, 20.0, 20.0, 0.0, 1.0);
@@ -248,8 +227,6 @@ impl App {
 //            }, Blend::Alpha);
 //        }
 
-        self.renderer.finish_frame().expect("a finished frame");
-
         Ok(())
     }
 }
@@ -263,7 +240,8 @@ fn raster(t: &mut GeometryTesselator, vertices: &mut Vec<Vertex>, color:Color, x
         v_min: 0,
         v_max: 128,
         layer: 0,
-        texture_size: 1024,
+        texture_width: 1024,
+        texture_height: 1024,
     };
 
     let texture_region_small = TextureRegion {
@@ -272,7 +250,8 @@ fn raster(t: &mut GeometryTesselator, vertices: &mut Vec<Vertex>, color:Color, x
         v_min: 16,
         v_max: 32,
         layer: 0,
-        texture_size: 1024,
+        texture_width: 1024,
+        texture_height: 1024,
     };
 
     t.color = color.float_raw();
@@ -0,0 +1,26 @@
+#version 150 core
+
+layout (std140)
+uniform Locals {
+	mat4 u_transform;
+	vec4 u_color;
+	float u_alpha_minimum;
+};
+
+// CompactVertex, normalized integers arrive as floats
+in vec3 position;
+in vec2 tex_coord;
+in float layer;
+in vec4 color;
+in vec4 normal;
+
+out vec4 v_color;
+out vec3 v_tex_coord;
+out vec3 v_normal;
+
+void main() {
+    gl_Position = u_transform * vec4(position, 1.0);
+    v_color = color * u_color;
+    v_tex_coord = vec3(tex_coord, layer);
+    v_normal = normal.xyz;
+}
@@ -0,0 +1,191 @@
+use std::thread;
+use std::time::Duration;
+
+use time;
+use aphid::Seconds;
+
+use {JamResult, JamError, InputState, Dimensions, Color, rgb};
+use render::gfx::OpenGLRenderer;
+
+// the callbacks a game implements to be driven by App
+pub trait AppState {
+    // called once per rendered frame, before any simulation steps, with this frame's input
+    fn input(&mut self, input: &InputState, dimensions: Dimensions);
+
+    // called zero or more times per frame, always with the fixed timestep
+    fn update(&mut self, dt: Seconds);
+
+    // alpha is how far we are between the last simulated step and the next (0.0 -> 1.0), for interpolation
+    fn render(&mut self, renderer: &mut OpenGLRenderer, alpha: f64) -> JamResult<()>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AppConfig {
+    pub timestep: Seconds,
+    pub target_fps: Option<u32>, // None (or Some(0)) -> don't sleep, rely on vsync
+    pub max_steps_per_frame: u32, // stops a slow frame turning into a spiral of death
+    pub clear_color: Color,
+}
+
+impl Default for AppConfig {
+    fn default() -> AppConfig {
+        AppConfig {
+            timestep: 1.0 / 60.0,
+            target_fps: Some(60),
+            max_steps_per_frame: 5,
+            clear_color: rgb(132, 193, 255),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FixedTimestep {
+    pub step: Seconds,
+    pub accumulator: Seconds,
+    pub max_steps: u32,
+}
+
+impl FixedTimestep {
+    // a step that isn't positive would never be used up
+    pub fn new(step: Seconds, max_steps: u32) -> JamResult<FixedTimestep> {
+        if !(step > 0.0) {
+            return Err(JamError::InvalidTimestep(step));
+        }
+        Ok(FixedTimestep {
+            step: step,
+            accumulator: 0.0,
+            max_steps: max_steps,
+        })
+    }
+
+    // returns how many fixed steps should be simulated for this much elapsed time
+    pub fn advance(&mut self, elapsed: Seconds) -> u32 {
+        self.accumulator += elapsed;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        if steps > self.max_steps {
+            // we've fallen too far behind, drop the time rather than trying to catch up
+            self.accumulator = 0.0;
+            steps = self.max_steps;
+        }
+
+        steps
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.step
+    }
+}
+
+pub fn seconds_between(from_ns: u64, to_ns: u64) -> Seconds {
+    ((to_ns - from_ns) as f64) / 1_000_000_000.0
+}
+
+// how long a frame may take at the target rate, None when there's nothing to wait for
+pub fn frame_budget(target_fps: Option<u32>) -> Option<Seconds> {
+    match target_fps {
+        Some(fps) if fps > 0 => Some(1.0 / (fps as f64)),
+        _ => None,
+    }
+}
+
+pub fn duration_from_seconds(seconds: Seconds) -> Duration {
+    let ns = (seconds.max(0.0) * 1_000_000_000.0) as u64;
+    Duration::new(ns / 1_000_000_000, (ns % 1_000_000_000) as u32)
+}
+
+pub struct App<S> where S : AppState {
+    pub state: S,
+    pub renderer: OpenGLRenderer,
+    pub config: AppConfig,
+    pub timestep: FixedTimestep,
+}
+
+impl<S> App<S> where S : AppState {
+    pub fn new(state: S, renderer: OpenGLRenderer, config: AppConfig) -> JamResult<App<S>> {
+        Ok(App {
+            state: state,
+            renderer: renderer,
+            config: config,
+            timestep: FixedTimestep::new(config.timestep, config.max_steps_per_frame)?,
+        })
+    }
+
+    // runs until the window is closed
+    pub fn run(&mut self) -> JamResult<()> {
+        let mut last_time = time::precise_time_ns();
+
+        'main: loop {
+            let frame_start = time::precise_time_ns();
+
+            let (dimensions, input_state) = self.renderer.begin_frame(self.config.clear_color);
+
+            if input_state.close {
+                break 'main;
+            }
+
+            let elapsed = seconds_between(last_time, frame_start);
+            last_time = frame_start;
+
+            self.state.input(&input_state, dimensions);
+
+            let steps = self.timestep.advance(elapsed);
+            for _ in 0..steps {
+                self.state.update(self.timestep.step);
+            }
+
+            let alpha = self.timestep.alpha();
+            self.state.render(&mut self.renderer, alpha)?;
+            self.renderer.finish_frame()?;
+
+            if let Some(frame_budget) = frame_budget(self.config.target_fps) {
+                let frame_time = seconds_between(frame_start, time::precise_time_ns());
+                if frame_time < frame_budget {
+                    thread::sleep(duration_from_seconds(frame_budget - frame_time));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_timestep_accumulates() {
+        let mut ts = FixedTimestep::new(0.1, 5).unwrap();
+        assert_eq!(ts.advance(0.05), 0);
+        assert_eq!(ts.advance(0.1), 1);
+        assert!((ts.alpha() - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn fixed_timestep_drops_excess() {
+        let mut ts = FixedTimestep::new(0.1, 5).unwrap();
+        assert_eq!(ts.advance(10.0), 5);
+        assert_eq!(ts.accumulator, 0.0);
+    }
+
+    #[test]
+    fn fixed_timestep_must_be_positive() {
+        assert!(FixedTimestep::new(0.0, 5).is_err());
+        assert!(FixedTimestep::new(-0.1, 5).is_err());
+        assert!(FixedTimestep::new(::std::f64::NAN, 5).is_err());
+    }
+
+    #[test]
+    fn frame_budget_ignores_zero_fps() {
+        assert_eq!(frame_budget(Some(0)), None);
+        assert_eq!(frame_budget(None), None);
+        assert_eq!(frame_budget(Some(50)), Some(0.02));
+        assert_eq!(duration_from_seconds(2.5), Duration::new(2, 500_000_000));
+    }
+}
@@ -186,7 +186,8 @@ pub fn build_font(full_path: &Path, font_description: &FontDescription, image_si
                         v_min: image_size - (write_y + pixel_height) as u32,
                         v_max: image_size - (write_y) as u32,
                         layer: 0,
-                        texture_size: image_size,
+                        texture_width: image_size,
+                        texture_height: image_size,
                     }),
                     advance: advance,
                 };
@@ -26,6 +26,14 @@ impl Dimensions {
             max: vec2(w as i32, h as i32)
         }
     }
-}
-
 
+    // dimensions of a sub rectangle given in pixels, points follow the window's scale
+    pub fn sub_dimensions(&self, rect: RectI) -> Dimensions {
+        let size = rect.size();
+        let scale = self.scale();
+        Dimensions {
+            pixels: (size.x as u32, size.y as u32),
+            points: (((size.x as f64) / scale) as u32, ((size.y as f64) / scale) as u32),
+        }
+    }
+}
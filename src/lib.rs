@@ -14,6 +14,13 @@ extern crate image;
 
 extern crate notify;
 extern crate rusttype;
+extern crate flate2;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate xml;
+extern crate base64;
 #[macro_use]
 extern crate aphid;
 
@@ -28,6 +35,11 @@ pub mod font;
 pub mod bitmap_font;
 pub mod dimensions;
 pub mod ui;
+pub mod app;
+pub mod viewport;
+
+#[cfg(test)]
+mod test_support;
 
 
 
@@ -39,6 +51,8 @@ pub use bitmap_font::*;
 pub use geometry::*;
 pub use input::*;
 pub use spring::*;
+pub use app::*;
+pub use viewport::*;
 
 use std::path::PathBuf;
 use std::path::Path;
@@ -64,6 +78,7 @@ pub enum JamError {
     BufferCreationError(gfx::buffer::CreationError),
     TextureCreationError(gfx::texture::CreationError),
     ResourceViewError(gfx::ResourceViewError),
+    TextureUpdateError(gfx::UpdateError<[u16; 3]>),
 
 //    ProgramLoadError(glium::ProgramCreationError),
 //    TextureLoadError(glium::texture::TextureCreationError),
@@ -74,6 +89,24 @@ pub enum JamError {
     MustLoadTextureBeforeFont,
     NoFiles,
     MismatchingDimensions, // path buf, expectation
+    TextureLayoutChanged, // files were added or removed, layers need reassigning
+    LoaderDisconnected, // a background load thread died without sending a result
+    ImageTooLargeToPack(String),
+    TooManyLayers,
+    UnknownTexture { name: String, similar: Vec<String> },
+    DuplicateSpriteName { name: String, first: PathBuf, second: PathBuf }, // e.g. hero.png and hero.aseprite
+    JsonError(serde_json::Error),
+    SpriteSheetError(PathBuf, String), // the sidecar, what's wrong with it
+    AsepriteError(PathBuf, String),
+    UnknownSpriteSheet(String),
+    UnknownSpriteTag { sheet: String, tag: String },
+    UnknownAnimation(String),
+    UnknownParticleEmitter(String),
+    TiledError(PathBuf, String), // the tmx or tsx, what's wrong with it
+    ObjError(PathBuf, String), // the obj or mtl, what's wrong with it
+    AutotileError(String), // the autotiler and tilemap it's applied to don't line up
+    TesselationWorkerPanicked,
+    InvalidTimestep(f64), // fixed timesteps must be positive
     RenderingPipelineIncomplete,
 }
 
@@ -89,6 +122,12 @@ impl From<image::ImageError> for JamError {
     }
 }
 
+impl From<serde_json::Error> for JamError {
+    fn from(err: serde_json::Error) -> Self {
+        JamError::JsonError(err)
+    }
+}
+
 impl From<io::Error> for JamError {
     fn from(val: io::Error) -> JamError {
         JamError::IO(val)
@@ -0,0 +1,300 @@
+use aphid::{HashMap, Seconds};
+
+use {JamResult, JamError};
+use render::texture_region::TextureRegion;
+use render::sprite_sheet::{SpriteSheet, TagDirection};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PlaybackMode {
+    Loop,
+    PingPong, // 0 1 2 1 0 1 2 ..., the end frames aren't repeated
+    OneShot, // holds the last frame once finished
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationFrame {
+    pub region: TextureRegion,
+    pub duration: Seconds,
+    pub events: Vec<String>, // fired when the frame is entered
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationClip {
+    pub name: String,
+    pub frames: Vec<AnimationFrame>,
+    pub mode: PlaybackMode,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, frames: Vec<AnimationFrame>, mode: PlaybackMode) -> AnimationClip {
+        AnimationClip {
+            name: name.to_string(),
+            frames: frames,
+            mode: mode,
+        }
+    }
+
+    // every frame shown for the same length of time
+    pub fn uniform(name: &str, regions: &[TextureRegion], frame_duration: Seconds, mode: PlaybackMode) -> AnimationClip {
+        AnimationClip::new(name, regions.iter().map(|r| {
+            AnimationFrame {
+                region: *r,
+                duration: frame_duration,
+                events: Vec::new(),
+            }
+        }).collect(), mode)
+    }
+
+    // a clip from a sheet's tag, default_duration covers frames without one (TexturePacker)
+    pub fn from_tag(sheet: &SpriteSheet, tag: &str, default_duration: Seconds) -> JamResult<AnimationClip> {
+        let direction = sheet.tag(tag).map(|t| t.direction).ok_or_else(|| JamError::UnknownSpriteTag { sheet: sheet.name.clone(), tag: tag.to_string() })?;
+        let mut frames : Vec<AnimationFrame> = sheet.tag_frames(tag)?.iter().map(|f| {
+            AnimationFrame {
+                region: f.region,
+                duration: f.duration.unwrap_or(default_duration),
+                events: Vec::new(),
+            }
+        }).collect();
+
+        let mode = match direction {
+            TagDirection::Forward => PlaybackMode::Loop,
+            TagDirection::Reverse => {
+                frames.reverse();
+                PlaybackMode::Loop
+            },
+            TagDirection::PingPong => PlaybackMode::PingPong,
+        };
+
+        Ok(AnimationClip::new(tag, frames, mode))
+    }
+
+    pub fn with_mode(self, mode: PlaybackMode) -> AnimationClip {
+        AnimationClip {
+            mode: mode,
+            .. self
+        }
+    }
+
+    // e.g. a footstep sound on frame 2
+    pub fn with_event(mut self, frame: usize, event: &str) -> AnimationClip {
+        if let Some(f) = self.frames.get_mut(frame) {
+            f.events.push(event.to_string());
+        }
+        self
+    }
+
+    // of one pass through the frames
+    pub fn duration(&self) -> Seconds {
+        self.frames.iter().map(|f| f.duration).sum()
+    }
+}
+
+// the clips of one sprite, by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationSet {
+    pub clips: HashMap<String, AnimationClip>,
+}
+
+impl AnimationSet {
+    pub fn new() -> AnimationSet {
+        AnimationSet {
+            clips: HashMap::default(),
+        }
+    }
+
+    // a clip per tag
+    pub fn from_sprite_sheet(sheet: &SpriteSheet, default_duration: Seconds) -> JamResult<AnimationSet> {
+        let mut set = AnimationSet::new();
+        for tag in &sheet.tags {
+            set.insert(AnimationClip::from_tag(sheet, &tag.name, default_duration)?);
+        }
+        Ok(set)
+    }
+
+    pub fn insert(&mut self, clip: AnimationClip) {
+        self.clips.insert(clip.name.clone(), clip);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AnimationClip> {
+        self.clips.get(name)
+    }
+
+    pub fn clip(&self, name: &str) -> JamResult<&AnimationClip> {
+        self.get(name).ok_or_else(|| JamError::UnknownAnimation(name.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AnimationEvent {
+    pub clip: String,
+    pub frame: usize,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationPlayer {
+    pub clip: AnimationClip,
+    pub frame: usize,
+    pub time_in_frame: Seconds,
+    pub speed: f64, // multiplies dt, 1.0 is the authored speed
+    pub forward: bool, // only changes for ping pong
+    pub finished: bool,
+    pub entered: bool, // whether the current frame's events have fired
+}
+
+impl AnimationPlayer {
+    pub fn new(clip: AnimationClip) -> AnimationPlayer {
+        AnimationPlayer {
+            clip: clip,
+            frame: 0,
+            time_in_frame: 0.0,
+            speed: 1.0,
+            forward: true,
+            finished: false,
+            entered: false,
+        }
+    }
+
+    // restarts from the first frame
+    pub fn play(&mut self, clip: AnimationClip) {
+        let speed = self.speed;
+        *self = AnimationPlayer {
+            speed: speed,
+            .. AnimationPlayer::new(clip)
+        };
+    }
+
+    // only restarts when it's a different clip, for calling every update
+    pub fn play_if_different(&mut self, clip: &AnimationClip) {
+        if self.clip.name != clip.name {
+            self.play(clip.clone());
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // None for an empty clip
+    pub fn current(&self) -> Option<TextureRegion> {
+        self.clip.frames.get(self.frame).map(|f| f.region)
+    }
+
+    pub fn region(&self) -> JamResult<TextureRegion> {
+        self.current().ok_or_else(|| JamError::UnknownAnimation(self.clip.name.clone()))
+    }
+
+    // returns the events of every frame entered, in order
+    pub fn advance(&mut self, dt: Seconds) -> Vec<AnimationEvent> {
+        let mut events = Vec::new();
+
+        if self.clip.frames.is_empty() {
+            return events;
+        }
+
+        if !self.entered {
+            self.entered = true;
+            self.push_events(&mut events);
+        }
+
+        // zero length clips would never leave the loop below
+        if self.finished || self.clip.duration() <= 0.0 {
+            return events;
+        }
+
+        self.time_in_frame += dt * self.speed;
+
+        while self.time_in_frame >= self.clip.frames[self.frame].duration {
+            self.time_in_frame -= self.clip.frames[self.frame].duration;
+            if !self.step() {
+                self.finished = true;
+                self.time_in_frame = 0.0;
+                break;
+            }
+            self.push_events(&mut events);
+        }
+
+        events
+    }
+
+    fn push_events(&self, events: &mut Vec<AnimationEvent>) {
+        for name in &self.clip.frames[self.frame].events {
+            events.push(AnimationEvent {
+                clip: self.clip.name.clone(),
+                frame: self.frame,
+                name: name.clone(),
+            });
+        }
+    }
+
+    // false when a one shot has run out of frames
+    fn step(&mut self) -> bool {
+        let count = self.clip.frames.len();
+        match self.clip.mode {
+            PlaybackMode::Loop => {
+                self.frame = (self.frame + 1) % count;
+                true
+            },
+            PlaybackMode::OneShot => {
+                if self.frame + 1 < count {
+                    self.frame += 1;
+                    true
+                } else {
+                    false
+                }
+            },
+            PlaybackMode::PingPong => {
+                if count > 1 {
+                    if self.forward && self.frame + 1 == count {
+                        self.forward = false;
+                    } else if !self.forward && self.frame == 0 {
+                        self.forward = true;
+                    }
+                    if self.forward {
+                        self.frame += 1;
+                    } else {
+                        self.frame -= 1;
+                    }
+                }
+                true
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions(n: u32) -> Vec<TextureRegion> {
+        (0..n).map(|i| TextureRegion { u_min: i * 8, u_max: i * 8 + 8, v_min: 0, v_max: 8, layer: 0, texture_width: 64, texture_height: 8 }).collect()
+    }
+
+    fn frames_visited(mode: PlaybackMode, steps: usize) -> Vec<usize> {
+        let mut player = AnimationPlayer::new(AnimationClip::uniform("walk", &regions(3), 0.1, mode));
+        (0..steps).map(|_| {
+            let frame = player.frame;
+            player.advance(0.1);
+            frame
+        }).collect()
+    }
+
+    #[test]
+    fn playback_modes() {
+        assert_eq!(frames_visited(PlaybackMode::Loop, 5), vec![0, 1, 2, 0, 1]);
+        assert_eq!(frames_visited(PlaybackMode::PingPong, 6), vec![0, 1, 2, 1, 0, 1]);
+        assert_eq!(frames_visited(PlaybackMode::OneShot, 5), vec![0, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn events_fire_on_entering_frames() {
+        let clip = AnimationClip::uniform("walk", &regions(3), 0.1, PlaybackMode::Loop).with_event(0, "start").with_event(2, "step");
+        let mut player = AnimationPlayer::new(clip);
+
+        let names = |events: Vec<AnimationEvent>| events.into_iter().map(|e| e.name).collect::<Vec<_>>();
+        assert_eq!(names(player.advance(0.05)), vec!["start"]);
+        assert_eq!(names(player.advance(0.2)), vec!["step"]);
+        assert_eq!(names(player.advance(0.1)), vec!["start"]);
+        assert_eq!(player.region().unwrap().u_min, 0);
+    }
+}
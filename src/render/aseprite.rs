@@ -0,0 +1,551 @@
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::ZlibDecoder;
+use image::{RgbaImage, Rgba};
+use aphid::Seconds;
+
+use {JamResult, JamError, load_file_contents};
+use render::texture_region::TextureRegion;
+use render::sprite_sheet::{SpriteSheet, SpriteFrame, FrameTag, TagDirection};
+
+pub const ASEPRITE_EXTENSIONS : [&'static str; 2] = ["aseprite", "ase"];
+
+const FILE_MAGIC : u16 = 0xA5E0;
+const FRAME_MAGIC : u16 = 0xF1FA;
+const HEADER_SIZE : usize = 128;
+
+const CHUNK_OLD_PALETTE : u16 = 0x0004;
+const CHUNK_LAYER : u16 = 0x2004;
+const CHUNK_CEL : u16 = 0x2005;
+const CHUNK_TAGS : u16 = 0x2018;
+const CHUNK_PALETTE : u16 = 0x2019;
+
+const LAYER_VISIBLE : u16 = 1;
+const LAYER_BACKGROUND : u16 = 8;
+const LAYER_TYPE_GROUP : u16 = 1;
+
+const HEADER_LAYER_OPACITY_VALID : u32 = 1;
+
+// every frame flattened to a full canvas image, ready for packing
+#[derive(Clone)]
+pub struct AsepriteFile {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<AsepriteFrame>,
+    pub tags: Vec<FrameTag>,
+}
+
+#[derive(Clone)]
+pub struct AsepriteFrame {
+    pub image: RgbaImage,
+    pub duration: Seconds,
+}
+
+impl AsepriteFile {
+    // regions are where each frame ended up in the texture array, in frame order
+    pub fn sprite_sheet(&self, name: &str, regions: &[TextureRegion]) -> SpriteSheet {
+        SpriteSheet {
+            name: name.to_string(),
+            image: name.to_string(),
+            frames: self.frames.iter().zip(regions.iter()).enumerate().map(|(i, (frame, region))| {
+                SpriteFrame {
+                    name: frame_name(name, i),
+                    region: *region,
+                    duration: Some(frame.duration),
+                    source_size: (self.width, self.height),
+                    trim_offset: (0, 0),
+                }
+            }).collect(),
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+// the name each frame is packed/looked up under, "characters/hero" -> "characters/hero/3"
+pub fn frame_name(sprite_name: &str, frame: usize) -> String {
+    format!("{}/{}", sprite_name, frame)
+}
+
+pub fn is_aseprite(path: &Path) -> bool {
+    path.extension().and_then(|p| p.to_str()).map(|s| {
+        let lower = s.to_lowercase();
+        ASEPRITE_EXTENSIONS.iter().any(|e| *e == lower)
+    }).unwrap_or(false)
+}
+
+pub fn load_aseprite(path: &Path) -> JamResult<AsepriteFile> {
+    let bytes = load_file_contents(path)?;
+    parse_aseprite(&bytes).map_err(|message| JamError::AsepriteError(path.to_path_buf(), message))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader {
+            bytes: bytes,
+            position: 0,
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.position + n > self.bytes.len() {
+            return Err(format!("unexpected end of data at {} reading {} bytes", self.position, n));
+        }
+        let slice = &self.bytes[self.position..(self.position + n)];
+        self.position += n;
+        Ok(slice)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let slice = &self.bytes[self.position.min(self.bytes.len())..];
+        self.position = self.bytes.len();
+        slice
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), String> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        self.take(1).map(|b| b[0])
+    }
+
+    // everything is little endian
+    fn u16(&mut self) -> Result<u16, String> {
+        self.take(2).map(|b| (b[0] as u16) | ((b[1] as u16) << 8))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        self.u16().map(|v| v as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.take(4).map(|b| (b[0] as u32) | ((b[1] as u32) << 8) | ((b[2] as u32) << 16) | ((b[3] as u32) << 24))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u16()? as usize;
+        self.take(length).map(|b| String::from_utf8_lossy(b).into_owned())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Layer {
+    visible: bool, // includes the visibility of parent groups
+    background: bool,
+    group: bool,
+    opacity: u8,
+}
+
+#[derive(Clone, Debug)]
+struct Cel {
+    layer: usize,
+    x: i32,
+    y: i32,
+    z_index: i32,
+    opacity: u8,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>, // in the file's colour depth, decoded once the palette is known
+}
+
+pub fn parse_aseprite(bytes: &[u8]) -> Result<AsepriteFile, String> {
+    let mut reader = Reader::new(bytes);
+
+    reader.skip(4)?; // file size
+    if reader.u16()? != FILE_MAGIC {
+        return Err("not an aseprite file".into());
+    }
+    let frame_count = reader.u16()? as usize;
+    let width = reader.u16()? as u32;
+    let height = reader.u16()? as u32;
+    let depth = reader.u16()?;
+    let flags = reader.u32()?;
+    reader.skip(2 + 4 + 4)?; // speed (deprecated), 2 zero dwords
+    let transparent_index = reader.u8()?;
+
+    if depth != 32 && depth != 16 && depth != 8 {
+        return Err(format!("unsupported colour depth {}", depth));
+    }
+    let bytes_per_pixel = (depth / 8) as usize;
+    let layer_opacity_valid = flags & HEADER_LAYER_OPACITY_VALID != 0;
+
+    reader.position = HEADER_SIZE;
+
+    let mut layers : Vec<Layer> = Vec::new();
+    let mut group_visibility : Vec<bool> = Vec::new(); // by child level
+    let mut palette : Vec<[u8; 4]> = Vec::new();
+    let mut has_new_palette = false;
+    let mut tags : Vec<FrameTag> = Vec::new();
+    let mut durations : Vec<Seconds> = Vec::new();
+    let mut frame_cels : Vec<Vec<Cel>> = Vec::new();
+
+    for frame_index in 0..frame_count {
+        let frame_start = reader.position;
+        let frame_size = reader.u32()? as usize;
+        if reader.u16()? != FRAME_MAGIC {
+            return Err(format!("bad magic for frame {}", frame_index));
+        }
+        let old_chunk_count = reader.u16()? as usize;
+        let duration_ms = reader.u16()?;
+        reader.skip(2)?;
+        let new_chunk_count = reader.u32()? as usize;
+        let chunk_count = if new_chunk_count == 0 { old_chunk_count } else { new_chunk_count };
+
+        let mut cels : Vec<Cel> = Vec::new();
+
+        for _ in 0..chunk_count {
+            let chunk_start = reader.position;
+            let chunk_size = reader.u32()? as usize;
+            let chunk_type = reader.u16()?;
+            if chunk_size < 6 {
+                return Err(format!("bad chunk size {} in frame {}", chunk_size, frame_index));
+            }
+            let mut chunk = Reader::new(reader.take(chunk_size - 6)?);
+
+            match chunk_type {
+                CHUNK_LAYER => {
+                    let layer_flags = chunk.u16()?;
+                    let layer_type = chunk.u16()?;
+                    let child_level = chunk.u16()? as usize;
+                    chunk.skip(2 + 2 + 2)?; // default width & height, blend mode
+                    let opacity = chunk.u8()?;
+
+                    group_visibility.truncate(child_level);
+                    let parent_visible = group_visibility.last().cloned().unwrap_or(true);
+                    let visible = parent_visible && layer_flags & LAYER_VISIBLE != 0;
+                    group_visibility.push(visible);
+
+                    layers.push(Layer {
+                        visible: visible,
+                        background: layer_flags & LAYER_BACKGROUND != 0,
+                        group: layer_type == LAYER_TYPE_GROUP,
+                        opacity: if layer_opacity_valid { opacity } else { 255 },
+                    });
+                },
+                CHUNK_CEL => {
+                    let layer = chunk.u16()? as usize;
+                    let x = chunk.i16()? as i32;
+                    let y = chunk.i16()? as i32;
+                    let opacity = chunk.u8()?;
+                    let cel_type = chunk.u16()?;
+                    let z_index = chunk.i16()? as i32;
+                    chunk.skip(5)?;
+
+                    match cel_type {
+                        0 | 2 => {
+                            let cel_width = chunk.u16()? as u32;
+                            let cel_height = chunk.u16()? as u32;
+                            // sizes come from the file, don't trust them to allocate
+                            if cel_width > width || cel_height > height {
+                                return Err(format!("cel in frame {} is {}x{}, larger than the {}x{} canvas", frame_index, cel_width, cel_height, width, height));
+                            }
+                            let expected = (cel_width * cel_height) as usize * bytes_per_pixel;
+                            let pixels = if cel_type == 0 {
+                                chunk.take(expected)?.to_vec()
+                            } else {
+                                let mut decoded = Vec::with_capacity(expected);
+                                ZlibDecoder::new(chunk.rest()).take(expected as u64).read_to_end(&mut decoded).map_err(|e| format!("cel decompression failed -> {:?}", e))?;
+                                decoded
+                            };
+                            if pixels.len() < expected {
+                                return Err(format!("cel in frame {} is missing pixels", frame_index));
+                            }
+                            cels.push(Cel {
+                                layer: layer,
+                                x: x,
+                                y: y,
+                                z_index: z_index,
+                                opacity: opacity,
+                                width: cel_width,
+                                height: cel_height,
+                                pixels: pixels,
+                            });
+                        },
+                        1 => {
+                            // linked cels reuse the pixels of an earlier frame's cel on the same layer
+                            let linked_frame = chunk.u16()? as usize;
+                            let linked = frame_cels.get(linked_frame).and_then(|fc| fc.iter().find(|c| c.layer == layer)).cloned();
+                            if let Some(linked_cel) = linked {
+                                cels.push(Cel {
+                                    x: x,
+                                    y: y,
+                                    z_index: z_index,
+                                    opacity: opacity,
+                                    .. linked_cel
+                                });
+                            }
+                        },
+                        _ => (), // tilemaps aren't supported, flatten them in the editor
+                    }
+                },
+                CHUNK_TAGS => {
+                    let tag_count = chunk.u16()?;
+                    chunk.skip(8)?;
+                    for _ in 0..tag_count {
+                        let from = chunk.u16()? as usize;
+                        let to = chunk.u16()? as usize;
+                        let direction = match chunk.u8()? {
+                            1 => TagDirection::Reverse,
+                            2 | 3 => TagDirection::PingPong, // we don't distinguish ping pong reverse
+                            _ => TagDirection::Forward,
+                        };
+                        chunk.skip(2 + 6 + 3 + 1)?; // repeat, reserved, colour, extra
+                        let name = chunk.string()?;
+                        tags.push(FrameTag {
+                            name: name,
+                            from: from,
+                            to: to,
+                            direction: direction,
+                        });
+                    }
+                },
+                CHUNK_PALETTE => {
+                    let size = chunk.u32()? as usize;
+                    let first = chunk.u32()? as usize;
+                    let last = chunk.u32()? as usize;
+                    chunk.skip(8)?;
+                    palette.resize(size.max(last + 1), [0, 0, 0, 0]);
+                    for index in first..(last + 1) {
+                        let entry_flags = chunk.u16()?;
+                        let colour = chunk.take(4)?;
+                        palette[index] = [colour[0], colour[1], colour[2], colour[3]];
+                        if entry_flags & 1 != 0 {
+                            chunk.string()?;
+                        }
+                    }
+                    has_new_palette = true;
+                },
+                CHUNK_OLD_PALETTE if !has_new_palette => {
+                    let packets = chunk.u16()?;
+                    let mut index = 0;
+                    for _ in 0..packets {
+                        index += chunk.u8()? as usize;
+                        let count = match chunk.u8()? {
+                            0 => 256,
+                            n => n as usize,
+                        };
+                        if palette.len() < index + count {
+                            palette.resize(index + count, [0, 0, 0, 0]);
+                        }
+                        for _ in 0..count {
+                            let colour = chunk.take(3)?;
+                            palette[index] = [colour[0], colour[1], colour[2], 255];
+                            index += 1;
+                        }
+                    }
+                },
+                _ => (),
+            }
+
+            reader.position = chunk_start + chunk_size;
+        }
+
+        reader.position = frame_start + frame_size;
+        durations.push((duration_ms as f64) / 1000.0);
+        frame_cels.push(cels);
+    }
+
+    for tag in &tags {
+        if tag.from > tag.to || tag.to >= frame_count {
+            return Err(format!("tag {:?} covers frames {}..{} but there are {}", tag.name, tag.from, tag.to, frame_count));
+        }
+    }
+
+    let frames = frame_cels.iter().zip(durations.iter()).map(|(cels, duration)| {
+        AsepriteFrame {
+            image: flatten(width, height, &layers, cels, depth, &palette, transparent_index),
+            duration: *duration,
+        }
+    }).collect();
+
+    Ok(AsepriteFile {
+        width: width,
+        height: height,
+        frames: frames,
+        tags: tags,
+    })
+}
+
+// composites the visible cels bottom to top, everything is treated as the normal blend mode
+fn flatten(width: u32, height: u32, layers: &[Layer], cels: &[Cel], depth: u16, palette: &[[u8; 4]], transparent_index: u8) -> RgbaImage {
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba { data: [0, 0, 0, 0] });
+
+    let mut ordered : Vec<&Cel> = cels.iter().filter(|c| {
+        layers.get(c.layer).map(|l| l.visible && !l.group).unwrap_or(false)
+    }).collect();
+    ordered.sort_by_key(|c| (c.layer as i32 + c.z_index, c.z_index));
+
+    for cel in ordered {
+        let layer = &layers[cel.layer];
+        let opacity = (cel.opacity as f32 / 255.0) * (layer.opacity as f32 / 255.0);
+
+        for cy in 0..cel.height {
+            for cx in 0..cel.width {
+                let x = cel.x + cx as i32;
+                let y = cel.y + cy as i32;
+                if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                    continue;
+                }
+
+                let i = (cy * cel.width + cx) as usize;
+                let src = match depth {
+                    32 => [cel.pixels[i * 4], cel.pixels[i * 4 + 1], cel.pixels[i * 4 + 2], cel.pixels[i * 4 + 3]],
+                    16 => [cel.pixels[i * 2], cel.pixels[i * 2], cel.pixels[i * 2], cel.pixels[i * 2 + 1]],
+                    _ => {
+                        let index = cel.pixels[i];
+                        if index == transparent_index && !layer.background {
+                            [0, 0, 0, 0]
+                        } else {
+                            palette.get(index as usize).cloned().unwrap_or([0, 0, 0, 0])
+                        }
+                    },
+                };
+
+                let dst = canvas.get_pixel_mut(x as u32, y as u32);
+                dst.data = blend_over(src, dst.data, opacity);
+            }
+        }
+    }
+
+    canvas
+}
+
+fn blend_over(src: [u8; 4], dst: [u8; 4], opacity: f32) -> [u8; 4] {
+    let sa = (src[3] as f32 / 255.0) * opacity;
+    let da = dst[3] as f32 / 255.0;
+    let out_a = sa + da * (1.0 - sa);
+    if out_a <= 0.0 {
+        return [0, 0, 0, 0];
+    }
+
+    let mut out = [0u8; 4];
+    for c in 0..3 {
+        let value = (src[c] as f32 * sa + dst[c] as f32 * da * (1.0 - sa)) / out_a;
+        out[c] = value.round().min(255.0) as u8;
+    }
+    out[3] = (out_a * 255.0).round() as u8;
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u16(out: &mut Vec<u8>, v: u16) {
+        out.push(v as u8);
+        out.push((v >> 8) as u8);
+    }
+
+    fn push_u32(out: &mut Vec<u8>, v: u32) {
+        push_u16(out, v as u16);
+        push_u16(out, (v >> 16) as u16);
+    }
+
+    fn chunk(chunk_type: u16, data: Vec<u8>) -> Vec<u8> {
+        let mut out = Vec::new();
+        push_u32(&mut out, data.len() as u32 + 6);
+        push_u16(&mut out, chunk_type);
+        out.extend(data);
+        out
+    }
+
+    fn layer(flags: u16, name: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        push_u16(&mut data, flags);
+        push_u16(&mut data, 0); // normal layer
+        push_u16(&mut data, 0); // child level
+        data.extend(vec![0; 6]);
+        data.push(255);
+        data.extend(vec![0; 3]);
+        push_u16(&mut data, name.len() as u16);
+        data.extend(name.as_bytes());
+        chunk(CHUNK_LAYER, data)
+    }
+
+    fn raw_cel(layer: u16, x: i16, pixel: [u8; 4]) -> Vec<u8> {
+        let mut data = Vec::new();
+        push_u16(&mut data, layer);
+        push_u16(&mut data, x as u16);
+        push_u16(&mut data, 0);
+        data.push(255);
+        push_u16(&mut data, 0); // raw
+        push_u16(&mut data, 0); // z index
+        data.extend(vec![0; 5]);
+        push_u16(&mut data, 1);
+        push_u16(&mut data, 1);
+        data.extend(&pixel);
+        chunk(CHUNK_CEL, data)
+    }
+
+    fn tags() -> Vec<u8> {
+        let mut data = Vec::new();
+        push_u16(&mut data, 1);
+        data.extend(vec![0; 8]);
+        push_u16(&mut data, 0);
+        push_u16(&mut data, 0);
+        data.push(2);
+        data.extend(vec![0; 12]);
+        push_u16(&mut data, 4);
+        data.extend(b"idle");
+        chunk(CHUNK_TAGS, data)
+    }
+
+    fn file(chunks: Vec<Vec<u8>>) -> Vec<u8> {
+        let mut frame = Vec::new();
+        let body : Vec<u8> = chunks.iter().flat_map(|c| c.iter().cloned()).collect();
+        push_u32(&mut frame, body.len() as u32 + 16);
+        push_u16(&mut frame, FRAME_MAGIC);
+        push_u16(&mut frame, chunks.len() as u16);
+        push_u16(&mut frame, 150);
+        push_u16(&mut frame, 0);
+        push_u32(&mut frame, chunks.len() as u32);
+        frame.extend(body);
+
+        let mut out = Vec::new();
+        push_u32(&mut out, (HEADER_SIZE + frame.len()) as u32);
+        push_u16(&mut out, FILE_MAGIC);
+        push_u16(&mut out, 1); // frames
+        push_u16(&mut out, 2); // width
+        push_u16(&mut out, 1); // height
+        push_u16(&mut out, 32);
+        push_u32(&mut out, HEADER_LAYER_OPACITY_VALID);
+        out.resize(HEADER_SIZE, 0);
+        out.extend(frame);
+        out
+    }
+
+    #[test]
+    fn flattens_visible_layers() {
+        let bytes = file(vec![
+            layer(LAYER_VISIBLE, "base"),
+            layer(0, "hidden"),
+            raw_cel(0, 1, [255, 0, 0, 255]),
+            raw_cel(1, 0, [0, 255, 0, 255]),
+            tags(),
+        ]);
+        let ase = parse_aseprite(&bytes).unwrap();
+
+        assert_eq!(ase.frames.len(), 1);
+        assert_eq!(ase.frames[0].duration, 0.15);
+        assert_eq!(ase.frames[0].image.get_pixel(0, 0).data, [0, 0, 0, 0]);
+        assert_eq!(ase.frames[0].image.get_pixel(1, 0).data, [255, 0, 0, 255]);
+        assert_eq!(ase.tags[0].name, "idle");
+        assert_eq!(ase.tags[0].direction, TagDirection::PingPong);
+    }
+
+    #[test]
+    fn rejects_cels_larger_than_the_canvas() {
+        let mut cel = raw_cel(0, 0, [255, 0, 0, 255]);
+        // the cel's width, after the 6 byte chunk header and 16 bytes of cel fields
+        cel[22] = 0xff;
+        cel[23] = 0xff;
+        let bytes = file(vec![layer(LAYER_VISIBLE, "base"), cel]);
+        assert!(parse_aseprite(&bytes).unwrap_err().contains("larger than"));
+    }
+}
@@ -0,0 +1,401 @@
+use aphid::{HashMap, HashSet};
+
+use {JamResult, JamError};
+use render::texture_region::{TextureRegion, TextureAtlasLayer};
+use render::tilemap::{Tile, Tilemap};
+
+// neighbour bits, north is the row above (y - 1)
+pub const NORTH : u8 = 1;
+pub const EAST : u8 = 2;
+pub const SOUTH : u8 = 4;
+pub const WEST : u8 = 8;
+
+// 8 bit (blob) bits, clockwise from north
+pub const BLOB_N : u8 = 1;
+pub const BLOB_NE : u8 = 2;
+pub const BLOB_E : u8 = 4;
+pub const BLOB_SE : u8 = 8;
+pub const BLOB_S : u8 = 16;
+pub const BLOB_SW : u8 = 32;
+pub const BLOB_W : u8 = 64;
+pub const BLOB_NW : u8 = 128;
+
+// wang corner bits, clockwise from north east
+pub const CORNER_NE : u8 = 1;
+pub const CORNER_SE : u8 = 2;
+pub const CORNER_SW : u8 = 4;
+pub const CORNER_NW : u8 = 8;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum AutotileKind {
+    FourBit, // edges only, 16 tiles
+    EightBit, // edges and corners, corners only count when both neighbouring edges match, 47 tiles
+    WangCorners, // 2 terrain corner set, 16 tiles, drawn on the dual grid so the output is a tile larger each way
+}
+
+impl AutotileKind {
+    // every mask this kind can produce, ascending
+    pub fn masks(&self) -> Vec<u8> {
+        match *self {
+            AutotileKind::FourBit | AutotileKind::WangCorners => (0..16).collect(),
+            AutotileKind::EightBit => blob_masks(),
+        }
+    }
+}
+
+// the 47 distinct blob masks, ascending
+pub fn blob_masks() -> Vec<u8> {
+    let mut masks : Vec<u8> = (0..256).map(|m| reduce_blob_mask(m as u8)).collect();
+    masks.sort();
+    masks.dedup();
+    masks
+}
+
+// drops corner bits without both adjacent edges
+pub fn reduce_blob_mask(mask: u8) -> u8 {
+    let mut reduced = mask & (BLOB_N | BLOB_E | BLOB_S | BLOB_W);
+    let corners = [(BLOB_NE, BLOB_N, BLOB_E), (BLOB_SE, BLOB_S, BLOB_E), (BLOB_SW, BLOB_S, BLOB_W), (BLOB_NW, BLOB_N, BLOB_W)];
+    for &(corner, a, b) in &corners {
+        if mask & corner != 0 && mask & a != 0 && mask & b != 0 {
+            reduced |= corner;
+        }
+    }
+    reduced
+}
+
+// terrain ids per cell, 0 is empty
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerrainGrid {
+    pub width: u32,
+    pub height: u32,
+    pub cells: Vec<u32>,
+}
+
+impl TerrainGrid {
+    pub fn new(width: u32, height: u32) -> TerrainGrid {
+        TerrainGrid {
+            width: width,
+            height: height,
+            cells: vec![0; (width * height) as usize],
+        }
+    }
+
+    // true -> terrain 1
+    pub fn from_bools(width: u32, height: u32, cells: &[bool]) -> TerrainGrid {
+        TerrainGrid {
+            width: width,
+            height: height,
+            cells: cells.iter().map(|&b| if b { 1 } else { 0 }).collect(),
+        }
+    }
+
+    pub fn get(&self, x: i64, y: i64) -> Option<u32> {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            None
+        } else {
+            Some(self.cells[(y * self.width as i64 + x) as usize])
+        }
+    }
+}
+
+// mask -> atlas cell for one terrain
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileRules {
+    pub tiles: HashMap<u8, Tile>,
+    pub fallback: Option<Tile>, // for masks without a rule
+}
+
+impl TileRules {
+    pub fn new() -> TileRules {
+        TileRules {
+            tiles: HashMap::default(),
+            fallback: None,
+        }
+    }
+
+    // the common layout, the nth mask of kind.masks() is the nth atlas cell from origin, wrapping after columns
+    pub fn sequential(kind: AutotileKind, origin: Tile, columns: u32) -> TileRules {
+        let mut rules = TileRules::new();
+        for (i, mask) in kind.masks().into_iter().enumerate() {
+            let i = i as u32;
+            rules.insert(mask, Tile { u: origin.u + i % columns, v: origin.v + i / columns });
+        }
+        rules
+    }
+
+    pub fn insert(&mut self, mask: u8, tile: Tile) {
+        self.tiles.insert(mask, tile);
+    }
+
+    pub fn with_fallback(self, tile: Tile) -> TileRules {
+        TileRules {
+            fallback: Some(tile),
+            .. self
+        }
+    }
+
+    pub fn lookup(&self, mask: u8) -> Option<Tile> {
+        self.tiles.get(&mask).cloned().or(self.fallback)
+    }
+}
+
+// keeps chosen tiles in step with a terrain grid, changing a cell only revisits its neighbours
+pub struct Autotiler {
+    pub kind: AutotileKind,
+    pub grid: TerrainGrid,
+    pub rules: HashMap<u32, TileRules>, // by terrain
+    pub out_of_bounds_matches: bool, // whether terrain continues off the edges of the grid
+    pub tiles: Vec<Option<Tile>>, // output_size, row major
+    pub changed: HashSet<(u32, u32)>,
+}
+
+impl Autotiler {
+    pub fn new(kind: AutotileKind, grid: TerrainGrid) -> Autotiler {
+        let mut autotiler = Autotiler {
+            kind: kind,
+            grid: grid,
+            rules: HashMap::default(),
+            out_of_bounds_matches: false,
+            tiles: Vec::new(),
+            changed: HashSet::default(),
+        };
+        autotiler.rebuild_all();
+        autotiler
+    }
+
+    pub fn with_rules(mut self, terrain: u32, rules: TileRules) -> Autotiler {
+        self.rules.insert(terrain, rules);
+        self.rebuild_all();
+        self
+    }
+
+    // the wang dual grid is a tile larger each way
+    pub fn output_size(&self) -> (u32, u32) {
+        match self.kind {
+            AutotileKind::WangCorners => (self.grid.width + 1, self.grid.height + 1),
+            _ => (self.grid.width, self.grid.height),
+        }
+    }
+
+    pub fn tile(&self, x: u32, y: u32) -> Option<Tile> {
+        let (w, h) = self.output_size();
+        if x < w && y < h {
+            self.tiles[(y * w + x) as usize]
+        } else {
+            None
+        }
+    }
+
+    pub fn region(&self, x: u32, y: u32, atlas: &TextureAtlasLayer) -> Option<TextureRegion> {
+        self.tile(x, y).map(|t| atlas.at(t.u, t.v))
+    }
+
+    // returns whether the terrain changed
+    pub fn set(&mut self, x: u32, y: u32, terrain: u32) -> bool {
+        if x >= self.grid.width || y >= self.grid.height {
+            return false;
+        }
+        let index = (y * self.grid.width + x) as usize;
+        if self.grid.cells[index] == terrain {
+            return false;
+        }
+        self.grid.cells[index] = terrain;
+
+        let (x, y) = (x as i64, y as i64);
+        let affected : Vec<(i64, i64)> = match self.kind {
+            AutotileKind::WangCorners => vec![(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)],
+            _ => (-1..2).flat_map(|dy| (-1..2).map(move |dx| (x + dx, y + dy))).collect(),
+        };
+
+        let (w, h) = self.output_size();
+        for (ax, ay) in affected {
+            if ax >= 0 && ay >= 0 && ax < w as i64 && ay < h as i64 {
+                self.update(ax as u32, ay as u32);
+            }
+        }
+        true
+    }
+
+    pub fn rebuild_all(&mut self) {
+        let (w, h) = self.output_size();
+        if self.tiles.len() != (w * h) as usize {
+            self.tiles = vec![None; (w * h) as usize];
+        }
+        for y in 0..h {
+            for x in 0..w {
+                self.update(x, y);
+            }
+        }
+    }
+
+    // tiles that changed since the last call
+    pub fn take_changes(&mut self) -> Vec<(u32, u32, Option<Tile>)> {
+        let mut changes : Vec<(u32, u32, Option<Tile>)> = self.changed.drain().map(|(x, y)| (x, y, None)).collect();
+        changes.sort_by_key(|&(x, y, _)| (y, x));
+        for change in changes.iter_mut() {
+            change.2 = self.tile(change.0, change.1);
+        }
+        changes
+    }
+
+    // copies changed tiles in to a tilemap layer (y -> z), only the touched chunks rebuild
+    // the tilemap must be output_size(), one larger each way than the grid for WangCorners
+    pub fn apply_to(&mut self, tilemap: &mut Tilemap, layer: usize) -> JamResult<()> {
+        let (w, h) = self.output_size();
+        if tilemap.width != w || tilemap.depth != h {
+            return Err(JamError::AutotileError(format!("tilemap is {}x{}, the autotiler outputs {}x{}", tilemap.width, tilemap.depth, w, h)));
+        }
+        if layer >= tilemap.layers.len() {
+            return Err(JamError::AutotileError(format!("tilemap has no layer {}", layer)));
+        }
+        for (x, y, tile) in self.take_changes() {
+            tilemap.set(layer, x, y, tile);
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, x: u32, y: u32) {
+        let tile = self.compute(x as i64, y as i64);
+        let (w, _) = self.output_size();
+        let index = (y * w + x) as usize;
+        if self.tiles[index] != tile {
+            self.tiles[index] = tile;
+            self.changed.insert((x, y));
+        }
+    }
+
+    fn matches(&self, x: i64, y: i64, terrain: u32) -> bool {
+        self.grid.get(x, y).map(|t| t == terrain).unwrap_or(self.out_of_bounds_matches)
+    }
+
+    fn compute(&self, x: i64, y: i64) -> Option<Tile> {
+        let (terrain, mask) = self.terrain_mask(x, y);
+        self.rules.get(&terrain).and_then(|rules| rules.lookup(mask))
+    }
+
+    fn terrain_mask(&self, x: i64, y: i64) -> (u32, u8) {
+        let mut mask = 0;
+        match self.kind {
+            AutotileKind::FourBit => {
+                let terrain = self.grid.get(x, y).unwrap_or(0);
+                let neighbours = [(0, -1, NORTH), (1, 0, EAST), (0, 1, SOUTH), (-1, 0, WEST)];
+                for &(dx, dy, bit) in &neighbours {
+                    if self.matches(x + dx, y + dy, terrain) {
+                        mask |= bit;
+                    }
+                }
+                (terrain, mask)
+            },
+            AutotileKind::EightBit => {
+                let terrain = self.grid.get(x, y).unwrap_or(0);
+                let neighbours = [(0, -1, BLOB_N), (1, -1, BLOB_NE), (1, 0, BLOB_E), (1, 1, BLOB_SE), (0, 1, BLOB_S), (-1, 1, BLOB_SW), (-1, 0, BLOB_W), (-1, -1, BLOB_NW)];
+                for &(dx, dy, bit) in &neighbours {
+                    if self.matches(x + dx, y + dy, terrain) {
+                        mask |= bit;
+                    }
+                }
+                (terrain, reduce_blob_mask(mask))
+            },
+            AutotileKind::WangCorners => {
+                // the dual tile at (x, y) sits on the corner shared by these cells
+                let corners = [(x, y - 1, CORNER_NE), (x, y, CORNER_SE), (x - 1, y, CORNER_SW), (x - 1, y - 1, CORNER_NW)];
+
+                // higher terrain ids draw over lower ones
+                let terrain = corners.iter().filter_map(|&(cx, cy, _)| self.grid.get(cx, cy)).max().unwrap_or(0);
+                for &(cx, cy, bit) in &corners {
+                    if self.matches(cx, cy, terrain) {
+                        mask |= bit;
+                    }
+                }
+                (terrain, mask)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn there_are_47_blob_tiles() {
+        assert_eq!(blob_masks().len(), 47);
+        assert_eq!(reduce_blob_mask(BLOB_NE), 0);
+        assert_eq!(reduce_blob_mask(BLOB_N | BLOB_E | BLOB_NE), BLOB_N | BLOB_E | BLOB_NE);
+    }
+
+    #[test]
+    fn four_bit_updates_neighbours_incrementally() {
+        let grid = TerrainGrid::new(3, 3);
+        let rules = TileRules::sequential(AutotileKind::FourBit, Tile { u: 0, v: 0 }, 4);
+        let mut autotiler = Autotiler::new(AutotileKind::FourBit, grid).with_rules(1, rules);
+        autotiler.take_changes();
+
+        autotiler.set(1, 1, 1);
+        assert_eq!(autotiler.tile(1, 1), Some(Tile { u: 0, v: 0 })); // alone, mask 0
+        assert_eq!(autotiler.take_changes().len(), 1);
+
+        autotiler.set(2, 1, 1);
+        assert_eq!(autotiler.tile(1, 1), Some(Tile { u: 2, v: 0 })); // east
+        assert_eq!(autotiler.tile(2, 1), Some(Tile { u: 0, v: 2 })); // west, mask 8
+        assert_eq!(autotiler.take_changes().len(), 2);
+    }
+
+    #[test]
+    fn applies_only_to_a_tilemap_of_the_output_size() {
+        use Vec3;
+        use render::Blend;
+        use render::texture_region::TextureAtlas;
+
+        let grid = TerrainGrid::new(3, 3);
+        let mut autotiler = Autotiler::new(AutotileKind::WangCorners, grid);
+        let atlas = TextureAtlas { texture_width: 64, texture_height: 64, tile_size: 16 };
+
+        let mut grid_sized = Tilemap::new(3, 3, 16, atlas, Vec3::new(1.0, 1.0, 1.0));
+        let layer = grid_sized.add_layer("ground", 0, 0.0, Blend::None);
+        assert!(autotiler.apply_to(&mut grid_sized, layer).is_err());
+
+        let mut output_sized = Tilemap::new(4, 4, 16, atlas, Vec3::new(1.0, 1.0, 1.0));
+        let layer = output_sized.add_layer("ground", 0, 0.0, Blend::None);
+        assert!(autotiler.apply_to(&mut output_sized, layer).is_ok());
+        assert!(autotiler.apply_to(&mut output_sized, layer + 1).is_err());
+    }
+
+    #[test]
+    fn blob_corners_need_both_edges() {
+        let grid = TerrainGrid::from_bools(3, 3, &[true; 9]);
+        let mut rules = TileRules::new().with_fallback(Tile { u: 9, v: 9 });
+        rules.insert(255, Tile { u: 0, v: 0 });
+        rules.insert(BLOB_E | BLOB_SE | BLOB_S, Tile { u: 1, v: 0 });
+        rules.insert(BLOB_E | BLOB_SE | BLOB_S | BLOB_SW | BLOB_W, Tile { u: 2, v: 0 });
+        let mut autotiler = Autotiler::new(AutotileKind::EightBit, grid).with_rules(1, rules);
+
+        assert_eq!(autotiler.tile(1, 1), Some(Tile { u: 0, v: 0 }));
+        assert_eq!(autotiler.tile(0, 0), Some(Tile { u: 1, v: 0 }));
+        assert_eq!(autotiler.tile(2, 2), Some(Tile { u: 9, v: 9 }));
+
+        // losing the north edge drops both north corners
+        autotiler.set(1, 0, 0);
+        assert_eq!(autotiler.tile(1, 1), Some(Tile { u: 2, v: 0 }));
+        assert_eq!(autotiler.tile(1, 0), None);
+    }
+
+    #[test]
+    fn wang_corners_use_the_dual_grid() {
+        let grid = TerrainGrid::from_bools(2, 2, &[true, false, false, false]);
+        let rules = TileRules::sequential(AutotileKind::WangCorners, Tile { u: 0, v: 0 }, 4);
+        let mut autotiler = Autotiler::new(AutotileKind::WangCorners, grid).with_rules(1, rules);
+
+        assert_eq!(autotiler.output_size(), (3, 3));
+        assert_eq!(autotiler.tile(0, 0), Some(Tile { u: 2, v: 0 })); // south east corner
+        assert_eq!(autotiler.tile(1, 0), Some(Tile { u: 0, v: 1 })); // south west
+        assert_eq!(autotiler.tile(1, 1), Some(Tile { u: 0, v: 2 })); // north west
+        assert_eq!(autotiler.tile(2, 2), None);
+        autotiler.take_changes();
+
+        autotiler.set(1, 1, 1);
+        assert_eq!(autotiler.tile(1, 1), Some(Tile { u: 2, v: 2 })); // north west and south east
+        assert_eq!(autotiler.tile(2, 2), Some(Tile { u: 0, v: 2 }));
+        assert_eq!(autotiler.take_changes().len(), 4);
+    }
+}
@@ -0,0 +1,107 @@
+use cgmath::{Rad, Point3, Vector3, Transform, SquareMatrix, Matrix, InnerSpace, Angle};
+
+use {Vec2, Vec3, Mat4};
+use render::Vertex;
+use render::quads::GeometryTesselator;
+use render::texture_region::TextureRegion;
+
+// faces a camera with the given pitch, the quad's local y is scaled by cos(pitch) so a billboard covers
+// the same screen height as an upright wall sprite of the same region
+pub fn billboard_transform(at: Vec3, pitch: Rad<f64>) -> Mat4 {
+    Mat4::from_translation(at) * Mat4::from_angle_x(-pitch) * Mat4::from_nonuniform_scale(1.0, pitch.cos(), 1.0)
+}
+
+// anchors are fractions of the region, (0.5, 0.0) is bottom centre, (0.5, 0.5) the middle
+impl GeometryTesselator {
+    // the region lies in the local x/y plane facing +z, offset so anchor is at the origin, before transform
+    fn draw_anchored_quad(&self, vertices: &mut Vec<Vertex>, tr: &TextureRegion, width: f64, height: f64, anchor: Vec2, transform: &Mat4) {
+        let layer_f = tr.layer as f32;
+
+        let x0 = -anchor.x * width;
+        let x1 = x0 + width;
+        let y0 = -anchor.y * height;
+        let y1 = y0 + height;
+
+        let p = |x: f64, y: f64| -> [f32; 3] {
+            let tp = transform.transform_point(Point3::new(x, y, 0.0));
+            [tp.x as f32, tp.y as f32, tp.z as f32]
+        };
+
+        // inverse transpose keeps normals perpendicular under non uniform scale
+        let normal_matrix = transform.invert().map(|m| m.transpose()).unwrap_or(*transform);
+        let n = normal_matrix.transform_vector(Vector3::new(0.0, 0.0, 1.0)).normalize();
+        let normal = [n.x as f32, n.y as f32, n.z as f32];
+
+        self.emit_quad(vertices, [
+            Vertex { position: p(x0, y0), tex_coord: [tr.nu_min(), tr.nv_min(), layer_f], color: self.color, normal: normal },
+            Vertex { position: p(x1, y0), tex_coord: [tr.nu_max(), tr.nv_min(), layer_f], color: self.color, normal: normal },
+            Vertex { position: p(x1, y1), tex_coord: [tr.nu_max(), tr.nv_max(), layer_f], color: self.color, normal: normal },
+            Vertex { position: p(x0, y1), tex_coord: [tr.nu_min(), tr.nv_max(), layer_f], color: self.color, normal: normal }
+        ]);
+    }
+
+    // sized by the tesselator scale like the wall variants, then placed by an arbitrary transform
+    pub fn draw_transformed(&self, vertices: &mut Vec<Vertex>, tr: &TextureRegion, anchor: Vec2, transform: Mat4) {
+        let width = (tr.width() as f64) * self.scale.x;
+        let height = (tr.height() as f64) * self.scale.y;
+        self.draw_anchored_quad(vertices, tr, width, height, anchor, &transform);
+    }
+
+    // always faces the camera, pass camera.pitch
+    pub fn draw_billboard(&self, vertices: &mut Vec<Vertex>, tr: &TextureRegion, at: Vec3, anchor: Vec2, pitch: Rad<f64>, depth_adjust: f64) {
+        let at = at + Vec3::new(0.0, depth_adjust, depth_adjust);
+        self.draw_transformed(vertices, tr, anchor, billboard_transform(at, pitch));
+    }
+
+    // spun around the anchor within the camera facing plane
+    pub fn draw_billboard_rotated(&self, vertices: &mut Vec<Vertex>, tr: &TextureRegion, at: Vec3, anchor: Vec2, pitch: Rad<f64>, theta: f64, depth_adjust: f64) {
+        let at = at + Vec3::new(0.0, depth_adjust, depth_adjust);
+        self.draw_transformed(vertices, tr, anchor, billboard_transform(at, pitch) * Mat4::from_angle_z(Rad(theta)));
+    }
+
+    // an x/y plane wall sprite spun around its anchor
+    pub fn draw_wall_rotated(&self, vertices: &mut Vec<Vertex>, tr: &TextureRegion, at: Vec3, anchor: Vec2, theta: f64, depth_adjust: f64) {
+        let at = at + Vec3::new(0.0, depth_adjust, depth_adjust);
+        self.draw_transformed(vertices, tr, anchor, Mat4::from_translation(at) * Mat4::from_angle_z(Rad(theta)));
+    }
+
+    // as draw_ui, spun around the anchor
+    pub fn draw_ui_rotated(&self, vertices: &mut Vec<Vertex>, tr: &TextureRegion, x: f64, y: f64, z: f64, anchor: Vec2, theta: f64, scale: f64) {
+        let width = (tr.width() as f64) * scale;
+        let height = (tr.height() as f64) * scale;
+        let transform = Mat4::from_translation(Vec3::new(x, y, z)) * Mat4::from_angle_z(Rad(theta));
+        self.draw_anchored_quad(vertices, tr, width, height, anchor, &transform);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camera::view;
+
+    #[test]
+    fn billboard_faces_the_camera() {
+        let pitch = Rad(::std::f64::consts::PI / 4.0);
+        let tr = TextureRegion { u_min: 0, u_max: 16, v_min: 0, v_max: 32, layer: 0, texture_width: 64, texture_height: 64 };
+        let t = GeometryTesselator::new(Vec3::new(1.0, 1.0, 1.0));
+
+        let mut billboard = Vec::new();
+        t.draw_billboard(&mut billboard, &tr, Vec3::new(3.0, 0.0, 2.0), Vec2::new(0.5, 0.0), pitch, 0.0);
+        let mut wall = Vec::new();
+        t.draw_wall_rotated(&mut wall, &tr, Vec3::new(3.0, 0.0, 2.0), Vec2::new(0.5, 0.0), 0.0, 0.0);
+
+        let v = view(pitch, Vec3::new(0.0, 0.0, 0.0));
+        let in_view = |vx: &Vertex| v.transform_point(Point3::new(vx.position[0] as f64, vx.position[1] as f64, vx.position[2] as f64));
+
+        // flat in view space, so square on to the camera
+        let z0 = in_view(&billboard[0]).z;
+        for vx in &billboard {
+            assert!((in_view(vx).z - z0).abs() < 0.0001);
+        }
+
+        // same on screen height as the upright wall
+        let billboard_height = in_view(&billboard[2]).y - in_view(&billboard[0]).y;
+        let wall_height = in_view(&wall[2]).y - in_view(&wall[0]).y;
+        assert!((billboard_height - wall_height).abs() < 0.0001);
+    }
+}
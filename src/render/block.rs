@@ -0,0 +1,249 @@
+use aphid::HashMap;
+
+use {Vec3, Vec3i};
+use render::Vertex;
+use render::quads::GeometryTesselator;
+use render::texture_region::TextureRegion;
+
+// north is -z (away from the default camera), east is +x
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BlockFace {
+    Top,
+    Bottom,
+    North,
+    South,
+    East,
+    West,
+}
+
+impl BlockFace {
+    pub fn all() -> [BlockFace; 6] {
+        [BlockFace::Top, BlockFace::Bottom, BlockFace::North, BlockFace::South, BlockFace::East, BlockFace::West]
+    }
+
+    // the neighbouring cell this face looks at
+    pub fn offset(&self) -> Vec3i {
+        match *self {
+            BlockFace::Top => Vec3i::new(0, 1, 0),
+            BlockFace::Bottom => Vec3i::new(0, -1, 0),
+            BlockFace::North => Vec3i::new(0, 0, -1),
+            BlockFace::South => Vec3i::new(0, 0, 1),
+            BlockFace::East => Vec3i::new(1, 0, 0),
+            BlockFace::West => Vec3i::new(-1, 0, 0),
+        }
+    }
+
+    pub fn normal(&self) -> [f32; 3] {
+        let o = self.offset();
+        [o.x as f32, o.y as f32, o.z as f32]
+    }
+
+    // corner, right and up in units of the block size, right x up is the normal so quads wind counter clockwise from outside
+    // sides are upright, the top reads like a floor tile, the bottom as seen from below
+    fn basis(&self) -> ([f64; 3], [f64; 3], [f64; 3]) {
+        match *self {
+            BlockFace::Top => ([0.0, 1.0, 1.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            BlockFace::Bottom => ([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            BlockFace::North => ([1.0, 0.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            BlockFace::South => ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            BlockFace::East => ([1.0, 0.0, 1.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            BlockFace::West => ([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BlockFaces {
+    pub top: TextureRegion,
+    pub bottom: TextureRegion,
+    pub north: TextureRegion,
+    pub south: TextureRegion,
+    pub east: TextureRegion,
+    pub west: TextureRegion,
+}
+
+impl BlockFaces {
+    pub fn uniform(tr: TextureRegion) -> BlockFaces {
+        BlockFaces::sides(tr, tr, tr)
+    }
+
+    // e.g. grass on top, dirt sides and bottom
+    pub fn sides(top: TextureRegion, side: TextureRegion, bottom: TextureRegion) -> BlockFaces {
+        BlockFaces {
+            top: top,
+            bottom: bottom,
+            north: side,
+            south: side,
+            east: side,
+            west: side,
+        }
+    }
+
+    pub fn face(&self, face: BlockFace) -> &TextureRegion {
+        match face {
+            BlockFace::Top => &self.top,
+            BlockFace::Bottom => &self.bottom,
+            BlockFace::North => &self.north,
+            BlockFace::South => &self.south,
+            BlockFace::East => &self.east,
+            BlockFace::West => &self.west,
+        }
+    }
+}
+
+impl GeometryTesselator {
+    // the region is stretched over the face, min is the lowest corner of the block
+    pub fn draw_block_face(&self, vertices: &mut Vec<Vertex>, tr: &TextureRegion, face: BlockFace, min: Vec3, size: Vec3) {
+        let layer_f = tr.layer as f32;
+        let (corner, right, up) = face.basis();
+        let normal = face.normal();
+
+        let p = |r: f64, u: f64| -> [f32; 3] {
+            [
+                (min.x + (corner[0] + right[0] * r + up[0] * u) * size.x) as f32,
+                (min.y + (corner[1] + right[1] * r + up[1] * u) * size.y) as f32,
+                (min.z + (corner[2] + right[2] * r + up[2] * u) * size.z) as f32,
+            ]
+        };
+
+        self.emit_quad(vertices, [
+            Vertex { position: p(0.0, 0.0), tex_coord: [tr.nu_min(), tr.nv_min(), layer_f], color: self.color, normal: normal },
+            Vertex { position: p(1.0, 0.0), tex_coord: [tr.nu_max(), tr.nv_min(), layer_f], color: self.color, normal: normal },
+            Vertex { position: p(1.0, 1.0), tex_coord: [tr.nu_max(), tr.nv_max(), layer_f], color: self.color, normal: normal },
+            Vertex { position: p(0.0, 1.0), tex_coord: [tr.nu_min(), tr.nv_max(), layer_f], color: self.color, normal: normal }
+        ]);
+    }
+
+    // all six faces of a box in world units, not scaled by the tesselator
+    pub fn draw_block(&self, vertices: &mut Vec<Vertex>, faces: &BlockFaces, min: Vec3, size: Vec3) {
+        for &face in BlockFace::all().iter() {
+            self.draw_block_face(vertices, faces.face(face), face, min, size);
+        }
+    }
+
+    // anchored at the bottom centre, like draw_wall_base_anchored
+    pub fn draw_cube(&self, vertices: &mut Vec<Vertex>, faces: &BlockFaces, at: Vec3, size: f64) {
+        let min = Vec3::new(at.x - size / 2.0, at.y, at.z - size / 2.0);
+        self.draw_block(vertices, faces, min, Vec3::new(size, size, size));
+    }
+
+    // only faces open to an empty cell are drawn, cells outside the grid count as empty
+    // mesh a chunk by passing its cell range, from inclusive to exclusive, neighbours outside it still cull
+    pub fn draw_voxels(&self, vertices: &mut Vec<Vertex>, grid: &VoxelGrid, blocks: &HashMap<u32, BlockFaces>, from: Vec3i, to: Vec3i, block_size: f64) {
+        let size = Vec3::new(block_size, block_size, block_size);
+        for z in from.z.max(0)..to.z.min(grid.size.z) {
+            for y in from.y.max(0)..to.y.min(grid.size.y) {
+                for x in from.x.max(0)..to.x.min(grid.size.x) {
+                    let at = Vec3i::new(x, y, z);
+                    let faces = match grid.get(at).and_then(|block| blocks.get(&block)) {
+                        Some(faces) => faces,
+                        None => continue,
+                    };
+                    let min = Vec3::new(x as f64, y as f64, z as f64) * block_size;
+                    for &face in BlockFace::all().iter() {
+                        if !grid.is_solid(at + face.offset()) {
+                            self.draw_block_face(vertices, faces.face(face), face, min, size);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// block types by cell, x then y then z
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelGrid {
+    pub size: Vec3i,
+    pub cells: Vec<Option<u32>>,
+}
+
+impl VoxelGrid {
+    pub fn new(size: Vec3i) -> VoxelGrid {
+        VoxelGrid {
+            size: size,
+            cells: vec![None; (size.x.max(0) * size.y.max(0) * size.z.max(0)) as usize],
+        }
+    }
+
+    pub fn contains(&self, at: Vec3i) -> bool {
+        at.x >= 0 && at.y >= 0 && at.z >= 0 && at.x < self.size.x && at.y < self.size.y && at.z < self.size.z
+    }
+
+    fn index(&self, at: Vec3i) -> usize {
+        ((at.z * self.size.y + at.y) * self.size.x + at.x) as usize
+    }
+
+    pub fn get(&self, at: Vec3i) -> Option<u32> {
+        if self.contains(at) {
+            self.cells[self.index(at)]
+        } else {
+            None
+        }
+    }
+
+    pub fn is_solid(&self, at: Vec3i) -> bool {
+        self.get(at).is_some()
+    }
+
+    // returns whether anything changed
+    pub fn set(&mut self, at: Vec3i, block: Option<u32>) -> bool {
+        if !self.contains(at) {
+            return false;
+        }
+        let index = self.index(at);
+        if self.cells[index] == block {
+            return false;
+        }
+        self.cells[index] = block;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    fn region() -> TextureRegion {
+        TextureRegion { u_min: 0, u_max: 16, v_min: 0, v_max: 16, layer: 0, texture_width: 64, texture_height: 64 }
+    }
+
+    #[test]
+    fn faces_wind_towards_their_normals() {
+        let t = GeometryTesselator::new(Vec3::new(1.0, 1.0, 1.0));
+        let mut vertices = Vec::new();
+        t.draw_cube(&mut vertices, &BlockFaces::uniform(region()), Vec3::new(0.0, 0.0, 0.0), 2.0);
+        assert_eq!(vertices.len(), 36);
+
+        for tri in vertices.chunks(3) {
+            let v = |i: usize| Vec3::new(tri[i].position[0] as f64, tri[i].position[1] as f64, tri[i].position[2] as f64);
+            let n = tri[0].normal;
+            let cross = (v(1) - v(0)).cross(v(2) - v(0));
+            assert!(cross.dot(Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64)) > 0.0);
+
+            // outward from the cube's centre
+            let centre = Vec3::new(0.0, 1.0, 0.0);
+            assert!((v(0) - centre).dot(Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn shared_faces_are_culled() {
+        let mut grid = VoxelGrid::new(Vec3i::new(4, 4, 4));
+        grid.set(Vec3i::new(1, 1, 1), Some(0));
+        grid.set(Vec3i::new(2, 1, 1), Some(0));
+        let mut blocks = HashMap::default();
+        blocks.insert(0, BlockFaces::uniform(region()));
+
+        let t = GeometryTesselator::new(Vec3::new(1.0, 1.0, 1.0));
+        let mut vertices = Vec::new();
+        t.draw_voxels(&mut vertices, &grid, &blocks, Vec3i::new(0, 0, 0), grid.size, 1.0);
+        assert_eq!(vertices.len(), 10 * 6);
+
+        // meshing only the first cell's chunk still culls against the second
+        let mut chunk = Vec::new();
+        t.draw_voxels(&mut chunk, &grid, &blocks, Vec3i::new(0, 0, 0), Vec3i::new(2, 4, 4), 1.0);
+        assert_eq!(chunk.len(), 5 * 6);
+    }
+}
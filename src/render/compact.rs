@@ -0,0 +1,97 @@
+use gfx::format::{U8Norm, I8Norm, U16Norm};
+
+use render::{Vertex, CompactVertex};
+use render::quads::GeometryTesselator;
+
+// uvs are clamped to 0..1, regions always are, so only hand built uvs that wrap lose anything
+pub fn pack_unit_u16(f: f32) -> U16Norm {
+    U16Norm((f.max(0.0).min(1.0) * 65535.0).round() as u16)
+}
+
+pub fn pack_unit_u8(f: f32) -> U8Norm {
+    U8Norm((f.max(0.0).min(1.0) * 255.0).round() as u8)
+}
+
+pub fn pack_signed_i8(f: f32) -> I8Norm {
+    I8Norm((f.max(-1.0).min(1.0) * 127.0).round() as i8)
+}
+
+impl CompactVertex {
+    pub fn from_vertex(v: &Vertex) -> CompactVertex {
+        CompactVertex {
+            position: v.position,
+            tex_coord: [pack_unit_u16(v.tex_coord[0]), pack_unit_u16(v.tex_coord[1])],
+            layer: v.tex_coord[2],
+            color: [pack_unit_u8(v.color[0]), pack_unit_u8(v.color[1]), pack_unit_u8(v.color[2]), pack_unit_u8(v.color[3])],
+            normal: [pack_signed_i8(v.normal[0]), pack_signed_i8(v.normal[1]), pack_signed_i8(v.normal[2]), I8Norm(0)],
+        }
+    }
+
+    // back to full floats, within the packing's precision
+    pub fn to_vertex(&self) -> Vertex {
+        Vertex {
+            position: self.position,
+            tex_coord: [(self.tex_coord[0].0 as f32) / 65535.0, (self.tex_coord[1].0 as f32) / 65535.0, self.layer],
+            color: [(self.color[0].0 as f32) / 255.0, (self.color[1].0 as f32) / 255.0, (self.color[2].0 as f32) / 255.0, (self.color[3].0 as f32) / 255.0],
+            normal: [(self.normal[0].0 as f32) / 127.0, (self.normal[1].0 as f32) / 127.0, (self.normal[2].0 as f32) / 127.0],
+        }
+    }
+}
+
+pub fn compact_vertices(vertices: &[Vertex]) -> Vec<CompactVertex> {
+    vertices.iter().map(CompactVertex::from_vertex).collect()
+}
+
+impl GeometryTesselator {
+    // runs any of the draw_ methods and appends their output packed, for upload_compact
+    // e.g. t.draw_compact(&mut out, |t, vs| t.draw_floor_tile(vs, &region, x, y, z, 0.0))
+    pub fn draw_compact<F>(&self, compact: &mut Vec<CompactVertex>, draw: F) where F : FnOnce(&GeometryTesselator, &mut Vec<Vertex>) {
+        let mut vertices = Vec::new();
+        draw(self, &mut vertices);
+        compact.extend(vertices.iter().map(CompactVertex::from_vertex));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+    use Vec3;
+    use render::texture_region::TextureRegion;
+
+    #[test]
+    fn packs_in_to_28_bytes() {
+        assert_eq!(mem::size_of::<CompactVertex>(), 28);
+        assert_eq!(mem::size_of::<Vertex>(), 52);
+    }
+
+    #[test]
+    fn round_trips_within_precision() {
+        let tr = TextureRegion { u_min: 3, u_max: 19, v_min: 7, v_max: 23, layer: 5, texture_width: 1024, texture_height: 1024 };
+        let mut t = GeometryTesselator::new(Vec3::new(1.0, 1.0, 1.0));
+        t.color = [0.25, 0.5, 0.75, 1.0];
+        t.push_rotate_x(::cgmath::Rad(0.3));
+
+        let mut full = Vec::new();
+        t.draw_floor_tile(&mut full, &tr, 0.0, 0.0, 0.0, 0.0);
+        let mut compact = Vec::new();
+        t.draw_compact(&mut compact, |t, vs| t.draw_floor_tile(vs, &tr, 0.0, 0.0, 0.0, 0.0));
+        assert_eq!(compact.len(), full.len());
+
+        for (c, f) in compact.iter().zip(full.iter()) {
+            let unpacked = c.to_vertex();
+            assert_eq!(unpacked.position, f.position);
+            assert_eq!(unpacked.tex_coord[2], 5.0);
+            for i in 0..2 {
+                // a texel of a 1024 texture is ~64 steps of u16
+                assert!((unpacked.tex_coord[i] - f.tex_coord[i]).abs() < 1.0 / 65535.0);
+            }
+            for i in 0..4 {
+                assert!((unpacked.color[i] - f.color[i]).abs() <= 0.5 / 255.0);
+            }
+            for i in 0..3 {
+                assert!((unpacked.normal[i] - f.normal[i]).abs() <= 0.5 / 127.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use {Vec3, Color};
+use color;
+use geometry::{LineSegment, Plane};
+use cgmath::InnerSpace;
+use render::Vertex;
+#[cfg(debug_assertions)]
+use render::quads::add_quad;
+use render::texture_region::TextureRegion;
+
+#[cfg(debug_assertions)]
+use std::f64::consts::PI;
+
+// immediate mode debug geometry, collected over a frame and drawn by Renderer::draw_debug
+// the drawing calls are no-ops when debug_assertions are off, so they can be left in release code
+pub struct DebugDraw {
+    pub depth_tested: Vec<Vertex>,
+    pub overlay: Vec<Vertex>, // drawn after clearing depth, so always on top
+    pub depth_test: bool, // applies to following calls
+    pub color: Color,
+    pub white: TextureRegion, // a solid white area of the UI texture array
+}
+
+#[cfg(debug_assertions)]
+const Y_POS : Vec3 = Vec3 { x: 0.0, y: 1.0, z: 0.0 };
+#[cfg(debug_assertions)]
+const X_POS : Vec3 = Vec3 { x: 1.0, y: 0.0, z: 0.0 };
+
+// any unit vector perpendicular to the given one
+#[cfg(debug_assertions)]
+fn perpendicular(v: Vec3) -> Vec3 {
+    if v.y.abs() < 0.99 {
+        v.cross(Y_POS).normalize()
+    } else {
+        v.cross(X_POS).normalize()
+    }
+}
+
+impl DebugDraw {
+    pub fn new(white: TextureRegion) -> DebugDraw {
+        DebugDraw {
+            depth_tested: Vec::new(),
+            overlay: Vec::new(),
+            depth_test: false,
+            color: color::WHITE,
+            white: white,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.depth_tested.is_empty() && self.overlay.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.depth_tested.clear();
+        self.overlay.clear();
+    }
+
+    #[cfg(debug_assertions)]
+    fn quad(&mut self, ps: [Vec3; 4], normal: Vec3) {
+        let u = self.white.nu_mid();
+        let v = self.white.nv_mid();
+        let layer_f = self.white.layer as f32;
+        let color = self.color.float_raw();
+        let n = [normal.x as f32, normal.y as f32, normal.z as f32];
+
+        let vertex = |p: Vec3| Vertex { position: [p.x as f32, p.y as f32, p.z as f32], tex_coord: [u, v, layer_f], color: color, normal: n };
+
+        let vertices = if self.depth_test { &mut self.depth_tested } else { &mut self.overlay };
+        add_quad(vertices, [vertex(ps[0]), vertex(ps[1]), vertex(ps[2]), vertex(ps[3])]);
+    }
+
+    // drawn as two crossed quads so it's visible from any angle
+    #[cfg(debug_assertions)]
+    pub fn line(&mut self, from: Vec3, to: Vec3, thickness: f64) {
+        let delta = to - from;
+        if delta.magnitude2() == 0.0 {
+            return;
+        }
+        let direction = delta.normalize();
+        let h = thickness / 2.0;
+        let a = perpendicular(direction) * h;
+        let b = direction.cross(a).normalize() * h;
+
+        self.quad([from - a, to - a, to + a, from + a], b.normalize());
+        self.quad([from - b, to - b, to + b, from + b], a.normalize());
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub fn line(&mut self, _: Vec3, _: Vec3, _: f64) {}
+
+    pub fn line_segment(&mut self, line: &LineSegment, thickness: f64) {
+        self.line(line.from, line.to, thickness)
+    }
+
+    #[cfg(debug_assertions)]
+    pub fn arrow(&mut self, from: Vec3, to: Vec3, thickness: f64, head_size: f64) {
+        let delta = to - from;
+        if delta.magnitude2() == 0.0 {
+            return;
+        }
+        self.line(from, to, thickness);
+
+        let direction = delta.normalize();
+        let a = perpendicular(direction);
+        let b = direction.cross(a).normalize();
+        let back = to - direction * head_size;
+
+        for &side in &[a, b] {
+            self.line(to, back + side * (head_size / 2.0), thickness);
+            self.line(to, back - side * (head_size / 2.0), thickness);
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub fn arrow(&mut self, _: Vec3, _: Vec3, _: f64, _: f64) {}
+
+    #[cfg(debug_assertions)]
+    pub fn wire_box(&mut self, min: Vec3, max: Vec3, thickness: f64) {
+        let corner = |x: bool, y: bool, z: bool| {
+            Vec3::new(if x { max.x } else { min.x }, if y { max.y } else { min.y }, if z { max.z } else { min.z })
+        };
+
+        for &a in &[false, true] {
+            for &b in &[false, true] {
+                self.line(corner(false, a, b), corner(true, a, b), thickness);
+                self.line(corner(a, false, b), corner(a, true, b), thickness);
+                self.line(corner(a, b, false), corner(a, b, true), thickness);
+            }
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub fn wire_box(&mut self, _: Vec3, _: Vec3, _: f64) {}
+
+    // circle around centre, in the plane with the given normal
+    #[cfg(debug_assertions)]
+    pub fn circle(&mut self, centre: Vec3, normal: Vec3, radius: f64, segments: u32, thickness: f64) {
+        let n = normal.normalize();
+        let a = perpendicular(n);
+        let b = n.cross(a).normalize();
+
+        let point = |i: u32| {
+            let theta = (i as f64) / (segments as f64) * 2.0 * PI;
+            centre + a * (theta.cos() * radius) + b * (theta.sin() * radius)
+        };
+
+        for i in 0..segments {
+            self.line(point(i), point(i + 1), thickness);
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub fn circle(&mut self, _: Vec3, _: Vec3, _: f64, _: u32, _: f64) {}
+
+    // a square grid of lines on the plane, centred on the point of the plane nearest the origin (or the given centre)
+    #[cfg(debug_assertions)]
+    pub fn grid(&mut self, centre: Vec3, normal: Vec3, spacing: f64, lines: u32, thickness: f64) {
+        let n = normal.normalize();
+        let a = perpendicular(n);
+        let b = n.cross(a).normalize();
+
+        let half = spacing * (lines as f64) / 2.0;
+        for i in 0..(lines + 1) {
+            let offset = (i as f64) * spacing - half;
+            self.line(centre + a * offset - b * half, centre + a * offset + b * half, thickness);
+            self.line(centre + b * offset - a * half, centre + b * offset + a * half, thickness);
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub fn grid(&mut self, _: Vec3, _: Vec3, _: f64, _: u32, _: f64) {}
+
+    pub fn plane(&mut self, plane: &Plane, spacing: f64, lines: u32, thickness: f64) {
+        let n = plane.normal.normalize();
+        let centre = n * (plane.coefficient / plane.normal.magnitude());
+        self.grid(centre, n, spacing, lines, thickness);
+        self.arrow(centre, centre + n * spacing, thickness, spacing / 4.0);
+    }
+
+    // a path through the points, such as a navigation route
+    pub fn path(&mut self, points: &[Vec3], thickness: f64) {
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], thickness);
+        }
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+
+    fn debug_draw() -> DebugDraw {
+        DebugDraw::new(TextureRegion { u_min: 0, u_max: 4, v_min: 0, v_max: 4, layer: 0, texture_width: 64, texture_height: 64 })
+    }
+
+    #[test]
+    fn lines_go_to_the_current_target() {
+        let mut d = debug_draw();
+        d.line(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.1);
+        assert_eq!(d.overlay.len(), 12);
+        assert!(d.depth_tested.is_empty());
+
+        d.depth_test = true;
+        d.wire_box(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), 0.1);
+        assert_eq!(d.depth_tested.len(), 12 * 12);
+
+        // zero length lines draw nothing
+        d.line(Vec3::new(1.0, 1.0, 1.0), Vec3::new(1.0, 1.0, 1.0), 0.1);
+        assert_eq!(d.depth_tested.len(), 12 * 12);
+
+        d.clear();
+        assert!(d.is_empty());
+    }
+}
@@ -11,8 +11,8 @@ use gfx::format::R8_G8_B8_A8;
 use color;
 use aphid::HashMap;
 
-use render::TextureArrayDimensions;
-use font::load_fonts_in_path;
+use render::{TextureArrayDimensions, TextureRegion, NamedRegions};
+use render::spawn_font_load;
 
 use {JamResult, JamError, InputState, Dimensions};
 
@@ -109,7 +109,41 @@ pub fn construct_opengl_renderer(file_resources: FileResources, dimensions: (u32
 //        ).expect("updating the texture");
 //    }
 
-    let fonts = load_fonts_in_path(file_resources.font_directory.path.as_path())?;
+    // the first layer is reserved for a small solid white area, for drawing untextured geometry (debug lines etc.)
+    let mut free_layers : Vec<u32> = (0..ui_store_dimensions.layers).collect();
+    let white_layer = free_layers.remove(0);
+    let white_size : u16 = 4;
+    let white_info = ImageInfoCommon {
+        xoffset: 0,
+        yoffset: 0,
+        zoffset: white_layer as u16,
+        width: white_size,
+        height: white_size,
+        depth: 1,
+        format: (),
+        mipmap: 0,
+    };
+    let white_data : Vec<[u8; 4]> = (0..(white_size * white_size)).map(|_| color::WHITE.raw()).collect();
+    encoder.update_texture::<R8_G8_B8_A8, Srgba8>(
+        &ui_tex,
+        None,
+        white_info,
+        &white_data,
+    ).expect("updating the texture");
+
+    // inset by a pixel so filtering never reaches outside the white area
+    let white_region = TextureRegion {
+        u_min: 1,
+        u_max: (white_size - 1) as u32,
+        v_min: 1,
+        v_max: (white_size - 1) as u32,
+        layer: white_layer,
+        texture_width: ui_size,
+        texture_height: ui_size,
+    };
+
+    // decoded in the background, text rasters blank until they arrive
+    let pending_fonts = spawn_font_load(file_resources.font_directory.path.clone());
 
 //    println!("ok how many loaded fonts -> {:?}", fonts.len());
 
@@ -119,8 +153,9 @@ pub fn construct_opengl_renderer(file_resources: FileResources, dimensions: (u32
         texture_view: ui_tex_view,
         elements: HashMap::default(),
         tick: 0,
-        free_layers: (0..ui_store_dimensions.layers).collect(),
-        fonts,
+        free_layers: free_layers,
+        fonts: Vec::new(),
+        white_region: white_region,
     };
 
     Ok(Renderer {
@@ -134,10 +169,24 @@ pub fn construct_opengl_renderer(file_resources: FileResources, dimensions: (u32
         screen_depth_target: main_depth,
         encoder: encoder,
         texture: None,
+        texture_dimensions: None,
+        texture_paths: Vec::new(),
+        texture_regions: NamedRegions::empty(),
+        sprite_sheets: HashMap::default(),
+        image_regions: NamedRegions::empty(),
+        animation_sheets: HashMap::default(),
+        changed_paths: Vec::new(),
+        pending_textures: None,
+        pending_texture_layers: None,
+        queued_texture_layers: Vec::new(),
+        pending_fonts: Some(pending_fonts),
         sampler,
         pipelines: None,
         dimensions,
         input_state: InputState::default(),
         ui: ui,
+        clip_stack: Vec::new(),
+        mask_stack: Vec::new(),
+        viewport_stack: Vec::new(),
     })
 }
\ No newline at end of file
@@ -18,6 +18,15 @@ gfx_defines! {
         normal: [f32; 3] = "normal",
     }
 
+    // 28 bytes to Vertex's 52, uvs must lie within 0..1, see render::compact
+    vertex CompactVertex {
+        position: [f32; 3] = "position",
+        tex_coord: [gfx::format::U16Norm; 2] = "tex_coord",
+        layer: f32 = "layer",
+        color: [gfx::format::U8Norm; 4] = "color",
+        normal: [gfx::format::I8Norm; 4] = "normal", // w is unused
+    }
+
     constant Locals {
         u_transform: [[f32; 4]; 4] = "u_transform",
         u_color: [f32; 4] = "u_color",
@@ -29,7 +38,8 @@ gfx_defines! {
         texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
         locals: gfx::ConstantBuffer<Locals> = "Locals",
         out_color: gfx::RenderTarget<ColorFormat> = "Target0",
-        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        out_depth: gfx::DepthStencilTarget<DepthFormat> = (gfx::preset::depth::LESS_EQUAL_WRITE, mask_test_stencil()),
+        scissor: gfx::Scissor = (),
     }
 
     pipeline pipe_blend {
@@ -37,13 +47,78 @@ gfx_defines! {
         texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
         locals: gfx::ConstantBuffer<Locals> = "Locals",
         out_color: gfx::BlendTarget<ColorFormat> = ("Target0", gfx::state::ColorMask::all(), gfx::preset::blend::ALPHA),
-        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        out_depth: gfx::DepthStencilTarget<DepthFormat> = (gfx::preset::depth::LESS_EQUAL_WRITE, mask_test_stencil()),
+        scissor: gfx::Scissor = (),
+    }
+
+    // for glows and particles, tests depth but doesn't write it so overlapping sprites all add up
+    pipeline pipe_add {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
+        locals: gfx::ConstantBuffer<Locals> = "Locals",
+        out_color: gfx::BlendTarget<ColorFormat> = ("Target0", gfx::state::ColorMask::all(), gfx::preset::blend::ADD),
+        out_depth: gfx::DepthStencilTarget<DepthFormat> = (gfx::preset::depth::LESS_EQUAL_TEST, mask_test_stencil()),
+        scissor: gfx::Scissor = (),
+    }
+
+    pipeline pipe_compact_opaque {
+        vbuf: gfx::VertexBuffer<CompactVertex> = (),
+        texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
+        locals: gfx::ConstantBuffer<Locals> = "Locals",
+        out_color: gfx::RenderTarget<ColorFormat> = "Target0",
+        out_depth: gfx::DepthStencilTarget<DepthFormat> = (gfx::preset::depth::LESS_EQUAL_WRITE, mask_test_stencil()),
+        scissor: gfx::Scissor = (),
+    }
+
+    pipeline pipe_compact_blend {
+        vbuf: gfx::VertexBuffer<CompactVertex> = (),
+        texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
+        locals: gfx::ConstantBuffer<Locals> = "Locals",
+        out_color: gfx::BlendTarget<ColorFormat> = ("Target0", gfx::state::ColorMask::all(), gfx::preset::blend::ALPHA),
+        out_depth: gfx::DepthStencilTarget<DepthFormat> = (gfx::preset::depth::LESS_EQUAL_WRITE, mask_test_stencil()),
+        scissor: gfx::Scissor = (),
+    }
+
+    pipeline pipe_compact_add {
+        vbuf: gfx::VertexBuffer<CompactVertex> = (),
+        texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
+        locals: gfx::ConstantBuffer<Locals> = "Locals",
+        out_color: gfx::BlendTarget<ColorFormat> = ("Target0", gfx::state::ColorMask::all(), gfx::preset::blend::ADD),
+        out_depth: gfx::DepthStencilTarget<DepthFormat> = (gfx::preset::depth::LESS_EQUAL_TEST, mask_test_stencil()),
+        scissor: gfx::Scissor = (),
     }
+
+    // writes only to the stencil buffer, used for pushing (incrementing) and popping (decrementing) masks
+    pipeline pipe_mask {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
+        locals: gfx::ConstantBuffer<Locals> = "Locals",
+        out_color: gfx::BlendTarget<ColorFormat> = ("Target0", gfx::state::ColorMask::empty(), gfx::preset::blend::ALPHA),
+        out_depth: gfx::DepthStencilTarget<DepthFormat> = (gfx::preset::depth::PASS_TEST, mask_write_stencil(gfx::state::StencilOp::IncrementClamp)),
+        scissor: gfx::Scissor = (),
+    }
+}
+
+
+// regular draws only pass where the stencil equals the current mask depth, with no masks that's 0 everywhere
+pub fn mask_test_stencil() -> gfx::state::Stencil {
+    use gfx::state::{Stencil, Comparison, StencilOp};
+    Stencil::new(Comparison::Equal, 0xFF, (StencilOp::Keep, StencilOp::Keep, StencilOp::Keep))
 }
 
+pub fn mask_write_stencil(op: gfx::state::StencilOp) -> gfx::state::Stencil {
+    use gfx::state::{Stencil, Comparison, StencilOp};
+    Stencil::new(Comparison::Equal, 0xFF, (StencilOp::Keep, StencilOp::Keep, op))
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GeometryBuffer<R> where R : gfx::Resources {
     pub buffer: gfx::handle::Buffer<R, Vertex>,
     pub slice : gfx::Slice<R>,
 }
+
+#[derive(Debug, Clone)]
+pub struct CompactGeometryBuffer<R> where R : gfx::Resources {
+    pub buffer: gfx::handle::Buffer<R, CompactVertex>,
+    pub slice : gfx::Slice<R>,
+}
@@ -10,18 +10,22 @@ use gfx_window_glutin;
 use gfx::texture::ImageInfoCommon;
 use gfx::format::R8_G8_B8_A8;
 
-use super::{Vertex, ColorFormat, DepthFormat, GeometryBuffer, Locals};
-use super::{pipe_blend, pipe_opaque, get_dimensions};
+use super::{Vertex, CompactVertex, ColorFormat, DepthFormat, GeometryBuffer, CompactGeometryBuffer, Locals};
+use super::{pipe_blend, pipe_opaque, pipe_add, pipe_mask, pipe_compact_opaque, pipe_compact_blend, pipe_compact_add, mask_write_stencil, get_dimensions};
 
-use {input, JamError, JamResult, color, Color};
-use render::{FileResources, FileWatcher, TextureArrayDimensions, Uniforms, Blend, TextureRegion, GeometryTesselator};
+use {input, JamError, JamResult, color, Color, clamp};
+use render::{FileResources, FileWatcher, TextureArrayDimensions, TextureArrayData, Uniforms, Blend, TextureRegion, GeometryTesselator, DebugDraw};
 use FontDirectory;
 use {Dimensions, InputState};
 use glutin::GlContext;
 use camera::ui_projection;
-use render::down_size_m4;
+use render::{down_size_m4, Transform};
+use render::{NamedRegions, SpriteSheet, is_sprite_sheet, is_aseprite, PendingLoad, LoadStatus, spawn_texture_load, spawn_texture_layer_load};
+use viewport::viewport_transform;
 
-use image::DynamicImage;
+use image::{DynamicImage, RgbaImage};
+use std::path::PathBuf;
+use std::mem;
 use notify::{RawEvent};
 use std::sync::mpsc::{Receiver};
 
@@ -29,7 +33,7 @@ use aphid::HashMap;
 
 use ui::*;
 
-use cgmath::{Vector2, vec3};
+use cgmath::{Vector2, Matrix4, vec3};
 
 use OurFont;
 
@@ -65,12 +69,29 @@ pub struct Renderer<R, C, F, D> where R : gfx::Resources,
 
     pub sampler: gfx::handle::Sampler<R>,
 
+    pub texture_dimensions: Option<TextureArrayDimensions>,
+    pub texture_paths: Vec<PathBuf>, // the file each layer of the texture came from (aseprite files repeat), empty when packed
+    pub texture_regions: NamedRegions, // sprite name lookup for the loaded texture
+    pub sprite_sheets: HashMap<String, SpriteSheet>, // from json exported alongside the images
+    pub image_regions: NamedRegions, // texture_regions without the sprite sheet json, sheet reloads start here
+    pub animation_sheets: HashMap<String, SpriteSheet>, // from aseprite files, kept across sheet reloads
+    pub changed_paths: Vec<PathBuf>, // files changed since the last frame began
+
+    pub pending_textures: Option<PendingLoad<TextureArrayData>>,
+    pub pending_texture_layers: Option<PendingLoad<Vec<(u32, RgbaImage)>>>,
+    pub queued_texture_layers: Vec<(u32, PathBuf)>, // changed while a layer load was running, loaded when it's done
+    pub pending_fonts: Option<PendingLoad<Vec<OurFont>>>,
+
     pub pipelines: Option<Pipelines<R>>,
 
     pub dimensions: Dimensions,
     pub input_state: InputState,
 
     pub ui: UI<R>,
+
+    pub clip_stack: Vec<RectI>, // pixels, origin bottom left, each entry is already intersected with the one below
+    pub mask_stack: Vec<(GeometryBuffer<R>, Uniforms)>, // kept so the mask can be removed from the stencil on pop
+    pub viewport_stack: Vec<RectI>, // pixels, origin bottom left, draws are squashed in to the top one
 }
 
 fn decode_color(c: Color) -> [f32; 4] {
@@ -93,6 +114,16 @@ pub struct UI<R> where R : gfx::Resources {
     pub tick: usize,
     pub free_layers: Vec<u32>,
     pub fonts: Vec<OurFont>,
+    pub white_region: TextureRegion, // reserved, never reclaimed
+}
+
+impl<R> UI<R> where R : gfx::Resources {
+    // frees every cached raster, they'll be redrawn on next use
+    pub fn reclaim_all(&mut self) {
+        for (_, entry) in self.elements.drain() {
+            self.free_layers.push(entry.texture_region.layer);
+        }
+    }
 }
 
 pub struct RasterElement {
@@ -105,6 +136,16 @@ pub struct RasterElement {
 pub struct Pipelines<R> where R : gfx::Resources {
     pub opaque: OpaquePipeline<R>,
     pub blend: BlendPipeline<R>,
+    pub add: AddPipeline<R>,
+    pub mask_push: MaskPipeline<R>,
+    pub mask_pop: MaskPipeline<R>,
+    pub compact: Option<CompactPipelines<R>>, // when FileResources has a compact shader
+}
+
+pub struct CompactPipelines<R> where R : gfx::Resources {
+    pub opaque: gfx::PipelineState<R, pipe_compact_opaque::Meta>,
+    pub blend: gfx::PipelineState<R, pipe_compact_blend::Meta>,
+    pub add: gfx::PipelineState<R, pipe_compact_add::Meta>,
 }
 
 pub struct OpaquePipeline<R> where R : gfx::Resources {
@@ -117,6 +158,14 @@ pub struct BlendPipeline<R> where R : gfx::Resources {
     pub data : Option<pipe_blend::Data<R>>,
 }
 
+pub struct AddPipeline<R> where R : gfx::Resources {
+    pub pipeline: gfx::PipelineState<R, pipe_add::Meta>,
+}
+
+pub struct MaskPipeline<R> where R : gfx::Resources {
+    pub pipeline: gfx::PipelineState<R, pipe_mask::Meta>,
+}
+
 impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_device_gl::Device> where F : gfx::Factory<gfx_device_gl::Resources> {
     pub fn begin_frame(&mut self, clear_color: Color) -> (Dimensions, InputState) {
         self.load_resources();
@@ -170,17 +219,23 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
             let decoded = decode_color(clear_color);
             self.encoder.clear(&self.screen_colour_target, decoded);
             self.encoder.clear_depth(&self.screen_depth_target, 1.0);
+            self.encoder.clear_stencil(&self.screen_depth_target, 0);
 //            let ad = self.screen_colour_target.get_dimensions();
 //            println!("internal dimensions -> {:?}", ad);
         }
 
+        self.clip_stack.clear();
+        self.mask_stack.clear();
+        self.viewport_stack.clear();
+
         (dimensions, self.input_state.clone())
     }
 
     pub fn load_resources(&mut self) {
-        let (reload_program, reload_texture) = check_reload(&self.file_watcher.change_events, &self.file_resources);
+        let reload = check_reload(&self.file_watcher.change_events, &self.file_resources);
+        self.changed_paths = reload.changed.clone();
 
-        if reload_program || self.pipelines.is_none() {
+        if reload.program || self.pipelines.is_none() {
 //            println!("LOAD PIPELINES");
             let pipeline_load_result = self.file_resources.shader_pair.load().and_then( |shader_data| {
                 let opaque_pso = self.factory.create_pipeline_simple(
@@ -195,6 +250,51 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
                     pipe_blend::new()
                 ).map_err(JamError::PipelineError)?;
 
+                let add_pso = self.factory.create_pipeline_simple(
+                    &shader_data.vertex_data,
+                    &shader_data.fragment_data,
+                    pipe_add::new()
+                ).map_err(JamError::PipelineError)?;
+
+                let mask_push_pso = self.factory.create_pipeline_simple(
+                    &shader_data.vertex_data,
+                    &shader_data.fragment_data,
+                    pipe_mask::new()
+                ).map_err(JamError::PipelineError)?;
+
+                let mask_pop_pso = self.factory.create_pipeline_simple(
+                    &shader_data.vertex_data,
+                    &shader_data.fragment_data,
+                    pipe_mask::Init {
+                        out_depth: (gfx::preset::depth::PASS_TEST, mask_write_stencil(gfx::state::StencilOp::DecrementClamp)),
+                        .. pipe_mask::new()
+                    }
+                ).map_err(JamError::PipelineError)?;
+
+                let compact = match self.file_resources.compact_shader_pair {
+                    Some(ref compact_pair) => {
+                        let compact_data = compact_pair.load()?;
+                        Some(CompactPipelines {
+                            opaque: self.factory.create_pipeline_simple(
+                                &compact_data.vertex_data,
+                                &compact_data.fragment_data,
+                                pipe_compact_opaque::new()
+                            ).map_err(JamError::PipelineError)?,
+                            blend: self.factory.create_pipeline_simple(
+                                &compact_data.vertex_data,
+                                &compact_data.fragment_data,
+                                pipe_compact_blend::new()
+                            ).map_err(JamError::PipelineError)?,
+                            add: self.factory.create_pipeline_simple(
+                                &compact_data.vertex_data,
+                                &compact_data.fragment_data,
+                                pipe_compact_add::new()
+                            ).map_err(JamError::PipelineError)?,
+                        })
+                    },
+                    None => None,
+                };
+
                 Ok(Pipelines {
                     opaque: OpaquePipeline {
                         pipeline: opaque_pso,
@@ -204,6 +304,16 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
                         pipeline: blend_pso,
                         data: None,
                     },
+                    add: AddPipeline {
+                        pipeline: add_pso,
+                    },
+                    mask_push: MaskPipeline {
+                        pipeline: mask_push_pso,
+                    },
+                    mask_pop: MaskPipeline {
+                        pipeline: mask_pop_pso,
+                    },
+                    compact: compact,
                 })
             });
 
@@ -213,35 +323,212 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
             }
         }
 
-        if reload_texture || self.texture.is_none() {
-//            println!("LOAD TEXTURES");
-            let texture_load_result = self.file_resources.texture_directory.load().and_then(|texture_array_data| {
-                let images_raw : Vec<_> = texture_array_data.images.iter().map(|img| {
-                    let dyn_image = DynamicImage::ImageRgba8(img.clone()).flipv();
-                    dyn_image.to_rgba().into_raw()
-//                    img.clone().into_raw()
-                } ).collect();
-                let data : Vec<_> = images_raw.iter().map(|v| v.as_slice()).collect();
+        let nothing_pending = self.pending_textures.is_none() && self.pending_texture_layers.is_none();
 
-                let kind = texture_kind_for(&texture_array_data.dimensions);
+        if self.texture.is_none() && nothing_pending {
+            self.start_texture_load();
+        } else if !reload.textures.is_empty() {
+            if let Err(e) = self.start_texture_layer_load(&reload.textures) {
+                println!("incremental texture reload not possible ({:?}), rebuilding", e);
+                self.start_texture_load();
+            } else if reload.textures.iter().any(|p| is_sprite_sheet(p)) {
+                self.reload_sprite_sheets();
+            }
+        }
 
-//                println!("kind -> {:?}", kind);
-//                let (texture, texture_view) = self.factory.create_texture_immutable_u8::<Rgba8>(kind, data.as_slice()).map_err(JamError::CombinedGFXError)?;
-                let (texture, texture_view) = self.factory.create_texture_immutable_u8::<Srgba8>(kind, data.as_slice()).map_err(JamError::CombinedGFXError)?;
+        self.receive_textures();
+        self.receive_texture_layers();
+        self.receive_fonts();
+    }
 
-                Ok((texture, texture_view))
-            });
+    // decoding happens on a worker thread, we keep drawing with the current texture until it's done
+    fn start_texture_load(&mut self) {
+        self.pending_texture_layers = None;
+        self.queued_texture_layers.clear();
+        self.pending_textures = Some(spawn_texture_load(&self.file_resources.texture_directory));
+    }
 
-            match texture_load_result {
-                Ok((t, tv)) => {
-                    let pair = (t, tv);
-                    self.texture = Some(pair);
-                },
-                Err(e) => println!("texture load error -> {:?}", e),
+    // errors if the whole array needs rebuilding
+    fn start_texture_layer_load(&mut self, changed: &[PathBuf]) -> JamResult<()> {
+        if self.pending_textures.is_some() {
+            // a full load is already under way but may have read the file already, so it's restarted
+            return Err(JamError::TextureLayoutChanged);
+        }
+
+        // aseprite frame counts can change with any save
+        if changed.iter().any(|p| is_aseprite(p)) {
+            return Err(JamError::TextureLayoutChanged);
+        }
+
+        // added or removed files shift the layers
+        let paths = self.file_resources.texture_directory.texture_paths()?;
+        let mut loaded_paths = self.texture_paths.clone();
+        loaded_paths.dedup();
+        if paths != loaded_paths {
+            return Err(JamError::TextureLayoutChanged);
+        }
+
+        let mut layers : Vec<(u32, PathBuf)> = Vec::new();
+        for changed_path in changed {
+            if let Some(layer) = self.texture_paths.iter().position(|p| changed_path.ends_with(p)) {
+                layers.push((layer as u32, self.texture_paths[layer].clone()));
+            }
+        }
+
+        if self.pending_texture_layers.is_some() {
+            // replacing the running load would lose its layers, so these wait for it
+            for layer in layers {
+                if !self.queued_texture_layers.contains(&layer) {
+                    self.queued_texture_layers.push(layer);
+                }
+            }
+        } else if !layers.is_empty() {
+            self.pending_texture_layers = Some(spawn_texture_layer_load(layers));
+        }
+
+        Ok(())
+    }
+
+    fn receive_textures(&mut self) {
+        let result = match self.pending_textures.as_ref().and_then(|p| p.poll()) {
+            Some(r) => r,
+            None => return,
+        };
+        self.pending_textures = None;
+
+        let texture_load_result = result.and_then(|texture_array_data| {
+            let kind = texture_kind_for(&texture_array_data.dimensions);
+            let bind = gfx::SHADER_RESOURCE;
+            let cty = gfx::format::ChannelType::Srgb;
+
+            // dynamic, so individual layers can be replaced on reload
+            let texture = self.factory.create_texture(kind, 1, bind, gfx::memory::Usage::Dynamic, Some(cty)).map_err(JamError::TextureCreationError)?;
+            let texture_view = self.factory.view_texture_as_shader_resource::<Srgba8>(&texture, (0, 0), gfx::format::Swizzle::new()).map_err(JamError::ResourceViewError)?;
+
+            for (layer, img) in texture_array_data.images.iter().enumerate() {
+                upload_texture_layer(&mut self.encoder, &texture, layer as u32, img)?;
+            }
+
+            Ok((texture, texture_view, texture_array_data))
+        });
+
+        match texture_load_result {
+            Ok((t, tv, data)) => {
+                let pair = (t, tv);
+                self.texture = Some(pair);
+                self.texture_dimensions = Some(data.dimensions);
+                self.texture_paths = data.paths;
+                self.texture_regions = data.regions;
+                self.sprite_sheets = data.sheets;
+                self.image_regions = data.image_regions;
+                self.animation_sheets = data.animations;
+            },
+            Err(e) => println!("texture load error -> {:?}", e),
+        }
+    }
+
+    fn receive_texture_layers(&mut self) {
+        let result = match self.pending_texture_layers.as_ref().and_then(|p| p.poll()) {
+            Some(r) => r,
+            None => return,
+        };
+        self.pending_texture_layers = None;
+
+        let upload_result = result.and_then(|layers| {
+            let dimensions = self.texture_dimensions.ok_or(JamError::NoTexture())?;
+            let texture = self.texture.as_ref().map(|&(ref t, _)| t.clone()).ok_or(JamError::NoTexture())?;
+
+            for (layer, img) in layers {
+                if img.dimensions() != (dimensions.width, dimensions.height) {
+                    return Err(JamError::MismatchingDimensions);
+                }
+                println!("reloading texture layer {:?}", layer);
+                upload_texture_layer(&mut self.encoder, &texture, layer, &img)?;
             }
+            Ok(())
+        });
+
+        if let Err(e) = upload_result {
+            println!("incremental texture reload failed ({:?}), rebuilding", e);
+            self.start_texture_load();
+        } else if !self.queued_texture_layers.is_empty() {
+            let queued = mem::replace(&mut self.queued_texture_layers, Vec::new());
+            self.pending_texture_layers = Some(spawn_texture_layer_load(queued));
         }
     }
 
+    // sheets only describe regions of the current texture, so there's nothing to upload
+    fn reload_sprite_sheets(&mut self) {
+        if self.texture.is_none() || self.pending_textures.is_some() {
+            return;
+        }
+
+        // starting from the current regions would keep frames of deleted or renamed sheets
+        match self.file_resources.texture_directory.load_sheets_over(&self.image_regions, &self.animation_sheets) {
+            Ok((regions, sheets)) => {
+                println!("reloaded sprite sheets -> {:?}", sheets.len());
+                self.texture_regions = regions;
+                self.sprite_sheets = sheets;
+            },
+            Err(e) => println!("sprite sheet reload error -> {:?}", e),
+        }
+    }
+
+    fn receive_fonts(&mut self) {
+        let result = match self.pending_fonts.as_ref().and_then(|p| p.poll()) {
+            Some(r) => r,
+            None => return,
+        };
+        self.pending_fonts = None;
+
+        match result {
+            Ok(fonts) => {
+                self.ui.fonts = fonts;
+                // anything rasterized before the fonts arrived is missing its text
+                self.ui.reclaim_all();
+            },
+            Err(e) => println!("font load error -> {:?}", e),
+        }
+    }
+
+    // e.g. "characters/hero" for textures/characters/hero.png, errors list similar names when missing
+    pub fn texture_region(&self, name: &str) -> JamResult<TextureRegion> {
+        self.texture_regions.region(name)
+    }
+
+    pub fn texture_layer(&self, name: &str) -> JamResult<u32> {
+        self.texture_regions.layer(name)
+    }
+
+    // e.g. "characters/hero" for textures/characters/hero.json
+    pub fn sprite_sheet(&self, name: &str) -> JamResult<&SpriteSheet> {
+        self.sprite_sheets.get(name).ok_or_else(|| JamError::UnknownSpriteSheet(name.to_string()))
+    }
+
+    // e.g. for TiledMapFile::reload_if_changed
+    pub fn changed_paths(&self) -> &[PathBuf] {
+        &self.changed_paths
+    }
+
+    // progress of all background loads, for loading screens
+    pub fn load_status(&self) -> LoadStatus {
+        let mut status = LoadStatus::none();
+        if let Some(ref p) = self.pending_textures {
+            status = status.combine(p.status());
+        }
+        if let Some(ref p) = self.pending_texture_layers {
+            status = status.combine(p.status());
+        }
+        if let Some(ref p) = self.pending_fonts {
+            status = status.combine(p.status());
+        }
+        status
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.pending_textures.is_some() || self.pending_texture_layers.is_some() || self.pending_fonts.is_some()
+    }
+
     pub fn clear_depth(&mut self) {
         self.encoder.clear_depth(&self.screen_depth_target, 1.0);
     }
@@ -254,6 +541,14 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
         }
     }
 
+    pub fn upload_compact(&mut self, vertices: &[CompactVertex]) -> CompactGeometryBuffer<gfx_device_gl::Resources> {
+        let (buffer, slice) = self.factory.create_vertex_buffer_with_slice(vertices, ());
+        CompactGeometryBuffer {
+            buffer,
+            slice,
+        }
+    }
+
 //    pub fn screenshot(&mut self) -> JamResult<()> { // -> image::DynamicImage
 //        let (width, height, depth, _) = self.screen_colour_target.get_dimensions();
 //        let pixels = (width as usize * height as usize * depth as usize);
@@ -281,7 +576,105 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
 //        Ok(())
 //    }
 
+    // restricts all following draws to this rect (in pixels, origin bottom left) intersected with any current clip
+    pub fn push_clip(&mut self, rect: RectI) {
+        let current = self.clip_stack.last().cloned().unwrap_or(self.dimensions.pixels_rect());
+        self.clip_stack.push(current.intersection(&rect));
+    }
+
+    pub fn pop_clip(&mut self) -> Option<RectI> {
+        self.clip_stack.pop()
+    }
+
+    // all following draws (including draw_view) render in to this rect of the window, and are clipped to it
+    pub fn push_viewport(&mut self, rect: RectI) {
+        self.push_clip(rect);
+        self.viewport_stack.push(rect);
+    }
+
+    pub fn pop_viewport(&mut self) -> Option<RectI> {
+        self.pop_clip();
+        self.viewport_stack.pop()
+    }
+
+    pub fn viewport(&self) -> RectI {
+        self.viewport_stack.last().cloned().unwrap_or(self.dimensions.pixels_rect())
+    }
+
+    fn locals_for(&self, uniforms: Uniforms) -> Locals {
+        let transform : Transform = if self.viewport_stack.is_empty() {
+            uniforms.transform
+        } else {
+            let into_viewport : Matrix4<f32> = down_size_m4(viewport_transform(self.viewport(), self.dimensions.pixels).into()).into();
+            (into_viewport * Matrix4::from(uniforms.transform)).into()
+        };
+        Locals {
+            u_transform: transform,
+            u_color: uniforms.color.float_raw(),
+            u_alpha_minimum: 0.01,
+        }
+    }
+
+    pub fn scissor(&self) -> gfx::Rect {
+        let clip = self.clip_stack.last().cloned().unwrap_or(self.dimensions.pixels_rect());
+        let size = clip.size();
+        gfx::Rect {
+            x: clamp(clip.min.x, 0, u16::max_value() as i32) as u16,
+            y: clamp(clip.min.y, 0, u16::max_value() as i32) as u16,
+            w: clamp(size.x, 0, u16::max_value() as i32) as u16,
+            h: clamp(size.y, 0, u16::max_value() as i32) as u16,
+        }
+    }
+
+    // restricts all following draws to the area covered by this geometry (and any masks already pushed)
+    pub fn push_mask(&mut self, geometry: &GeometryBuffer<gfx_device_gl::Resources>, uniforms: Uniforms) -> JamResult<()> {
+        self.draw_mask(geometry, uniforms, true)?;
+        self.mask_stack.push((geometry.clone(), uniforms));
+        Ok(())
+    }
+
+    pub fn pop_mask(&mut self) -> JamResult<()> {
+        if let Some((geometry, uniforms)) = self.mask_stack.pop() {
+            self.draw_mask(&geometry, uniforms, false)?;
+        }
+        Ok(())
+    }
+
+    fn draw_mask(&mut self, geometry: &GeometryBuffer<gfx_device_gl::Resources>, uniforms: Uniforms, push: bool) -> JamResult<()> {
+        let tv = self.texture.as_ref().map(|&(_, ref v)| v).ok_or(JamError::NoTexture())?;
+        let depth = self.mask_depth();
+        let scissor = self.scissor();
+        let locals = self.locals_for(uniforms);
+
+        let pipelines = self.pipelines.as_ref().ok_or(JamError::NoPipeline())?;
+        let mask_pipe = if push { &pipelines.mask_push } else { &pipelines.mask_pop };
+
+        // popping runs against the stencil values the push produced
+        let reference = if push { depth } else { depth + 1 };
+
+        let mask_data = pipe_mask::Data {
+            vbuf: geometry.buffer.clone(),
+            texture: (tv.clone(), self.sampler.clone()),
+            locals: self.factory.create_constant_buffer(1),
+            out_color: self.screen_colour_target.clone(),
+            out_depth: (self.screen_depth_target.clone(), (reference, reference)),
+            scissor: scissor,
+        };
+        self.encoder.update_constant_buffer(&mask_data.locals, &locals);
+        self.encoder.draw(&geometry.slice, &mask_pipe.pipeline, &mask_data);
+
+        Ok(())
+    }
+
+    pub fn mask_depth(&self) -> u8 {
+        self.mask_stack.len() as u8
+    }
+
     fn draw_raw(&mut self, geometry: &GeometryBuffer<gfx_device_gl::Resources>, uniforms: Uniforms, blend:Blend, texture_array:TextureArraySource)  -> JamResult<()> {
+        let scissor = self.scissor();
+        let stencil_reference = self.mask_depth();
+        let locals = self.locals_for(uniforms);
+
         let tv = match texture_array {
             TextureArraySource::UI => &self.ui.texture_view,
             TextureArraySource::Primary => self.texture.as_ref().map(|&(_, ref v)| v).ok_or(JamError::NoTexture())?,
@@ -297,18 +690,24 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
                     texture: (tv.clone(), self.sampler.clone()),
                     locals: self.factory.create_constant_buffer(1),
                     out_color: self.screen_colour_target.clone(),
-                    out_depth: self.screen_depth_target.clone(),
-                };
-                let locals = Locals {
-                    u_transform: uniforms.transform,
-                    u_color: uniforms.color.float_raw(),
-                    u_alpha_minimum: 0.01,
+                    out_depth: (self.screen_depth_target.clone(), (stencil_reference, stencil_reference)),
+                    scissor: scissor,
                 };
                 self.encoder.update_constant_buffer(&opaque_data.locals, &locals);
                 self.encoder.draw(&geometry.slice, &opaque_pipe.pipeline, &opaque_data);
             },
             Blend::Add => {
-                //                println!("no add pipeline atm")
+                let add_pipe = self.pipelines.as_mut().ok_or(JamError::NoPipeline()).map(|p| &mut p.add )?;
+                let add_data = pipe_add::Data {
+                    vbuf: geometry.buffer.clone(),
+                    texture: (tv.clone(), self.sampler.clone()),
+                    locals: self.factory.create_constant_buffer(1),
+                    out_color: self.screen_colour_target.clone(),
+                    out_depth: (self.screen_depth_target.clone(), (stencil_reference, stencil_reference)),
+                    scissor: scissor,
+                };
+                self.encoder.update_constant_buffer(&add_data.locals, &locals);
+                self.encoder.draw(&geometry.slice, &add_pipe.pipeline, &add_data);
             },
             Blend::Alpha => {
                 let blend_pipe = self.pipelines.as_mut().ok_or(JamError::NoPipeline()).map(|p| &mut p.blend )?;
@@ -317,12 +716,8 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
                     texture: (tv.clone(), self.sampler.clone()),
                     locals: self.factory.create_constant_buffer(1),
                     out_color: self.screen_colour_target.clone(),
-                    out_depth: self.screen_depth_target.clone(),
-                };
-                let locals = Locals {
-                    u_transform: uniforms.transform,
-                    u_color: uniforms.color.float_raw(),
-                    u_alpha_minimum: 0.01,
+                    out_depth: (self.screen_depth_target.clone(), (stencil_reference, stencil_reference)),
+                    scissor: scissor,
                 };
                 self.encoder.update_constant_buffer(&blend_data.locals, &locals);
                 self.encoder.draw(&geometry.slice, &blend_pipe.pipeline, &blend_data);
@@ -336,12 +731,94 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
         self.draw_raw(geometry, uniforms, blend, TextureArraySource::Primary)
     }
 
+    // as draw, for geometry uploaded with upload_compact
+    pub fn draw_compact(&mut self, geometry: &CompactGeometryBuffer<gfx_device_gl::Resources>, uniforms: Uniforms, blend:Blend) -> JamResult<()> {
+        let scissor = self.scissor();
+        let stencil_reference = self.mask_depth();
+        let locals = self.locals_for(uniforms);
+
+        let tv = self.texture.as_ref().map(|&(_, ref v)| v).ok_or(JamError::NoTexture())?;
+        let compact = self.pipelines.as_ref().ok_or(JamError::NoPipeline()).and_then(|p| p.compact.as_ref().ok_or(JamError::NoPipeline()))?;
+
+        match blend {
+            Blend::None => {
+                let data = pipe_compact_opaque::Data {
+                    vbuf: geometry.buffer.clone(),
+                    texture: (tv.clone(), self.sampler.clone()),
+                    locals: self.factory.create_constant_buffer(1),
+                    out_color: self.screen_colour_target.clone(),
+                    out_depth: (self.screen_depth_target.clone(), (stencil_reference, stencil_reference)),
+                    scissor: scissor,
+                };
+                self.encoder.update_constant_buffer(&data.locals, &locals);
+                self.encoder.draw(&geometry.slice, &compact.opaque, &data);
+            },
+            Blend::Add => {
+                let data = pipe_compact_add::Data {
+                    vbuf: geometry.buffer.clone(),
+                    texture: (tv.clone(), self.sampler.clone()),
+                    locals: self.factory.create_constant_buffer(1),
+                    out_color: self.screen_colour_target.clone(),
+                    out_depth: (self.screen_depth_target.clone(), (stencil_reference, stencil_reference)),
+                    scissor: scissor,
+                };
+                self.encoder.update_constant_buffer(&data.locals, &locals);
+                self.encoder.draw(&geometry.slice, &compact.add, &data);
+            },
+            Blend::Alpha => {
+                let data = pipe_compact_blend::Data {
+                    vbuf: geometry.buffer.clone(),
+                    texture: (tv.clone(), self.sampler.clone()),
+                    locals: self.factory.create_constant_buffer(1),
+                    out_color: self.screen_colour_target.clone(),
+                    out_depth: (self.screen_depth_target.clone(), (stencil_reference, stencil_reference)),
+                    scissor: scissor,
+                };
+                self.encoder.update_constant_buffer(&data.locals, &locals);
+                self.encoder.draw(&geometry.slice, &compact.blend, &data);
+            },
+        }
+
+        Ok(())
+    }
+
     pub fn draw_vertices(&mut self, vertices: &[Vertex], uniforms: Uniforms, blend:Blend) -> JamResult<GeometryBuffer<gfx_device_gl::Resources>> {
         let geometry = self.upload(vertices);
         let res = self.draw(&geometry, uniforms, blend);
         res.map(|()| geometry)
     }
 
+    pub fn debug_draw(&self) -> DebugDraw {
+        DebugDraw::new(self.ui.white_region)
+    }
+
+    // draws and then clears everything collected this frame, overlay last after clearing depth
+    pub fn draw_debug(&mut self, debug: &mut DebugDraw, transform: Transform) -> JamResult<()> {
+        if debug.is_empty() {
+            return Ok(());
+        }
+
+        let uniforms = Uniforms {
+            transform: transform,
+            color: color::WHITE,
+        };
+
+        if !debug.depth_tested.is_empty() {
+            let geo = self.upload(&debug.depth_tested);
+            self.draw_raw(&geo, uniforms, Blend::Alpha, TextureArraySource::UI)?;
+        }
+
+        if !debug.overlay.is_empty() {
+            self.clear_depth();
+            let geo = self.upload(&debug.overlay);
+            self.draw_raw(&geo, uniforms, Blend::Alpha, TextureArraySource::UI)?;
+        }
+
+        debug.clear();
+
+        Ok(())
+    }
+
     pub fn finish_frame(&mut self) -> JamResult<()> {
         self.encoder.flush(&mut self.device);
         self.window.swap_buffers().map_err(JamError::ContextError)?;
@@ -352,14 +829,13 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
     pub fn draw_view<Ev>(&mut self, view:&View<Ev>) -> JamResult<()> {
         let tick = self.ui.tick;
         let store_texture_size = self.ui.dimensions;
-        let mut vertices = Vec::new();
 
-        let tesselator = GeometryTesselator {
-            scale: vec3(1.0, 1.0, 1.0),
-            color: color::WHITE.float_raw(),
-        };
+        // consecutive layers sharing a clip are drawn together
+        let mut batches : Vec<(Option<RectI>, Vec<Vertex>)> = Vec::new();
 
-        for (layer, rect_abs, (v_z, l_z)) in view.layer_iter() {
+        let tesselator = GeometryTesselator::new(vec3(1.0, 1.0, 1.0));
+
+        for (layer, rect_abs, (v_z, l_z), clip) in view.layer_iter() {
             let size = rect_abs.size();
             let sized_element = ElementWithSize {
                 element: layer.content.clone(),
@@ -385,7 +861,8 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
                         v_min: 0,
                         v_max: img.height() as u32,
                         layer: use_layer as u32,
-                        texture_size: store_texture_size.width,
+                        texture_width: store_texture_size.width,
+                        texture_height: store_texture_size.height,
                     };
                     let re = RasterElement {
                         translation: translation, // translation from requested origin to output area
@@ -418,11 +895,19 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
             let position = raster_translation + rect_abs.min;
             let z = (v_z as f64) * 1.0 + (l_z as f64) * 0.1;
 
-            tesselator.draw_ui(&mut vertices, &region, position.x as f64, position.y as f64, z, 1.0);
+            let same_clip = batches.last().map(|&(ref c, _)| *c == clip).unwrap_or(false);
+            if !same_clip {
+                batches.push((clip, Vec::new()));
+            }
+            let vertices = &mut batches.last_mut().expect("a batch").1;
+
+            tesselator.draw_ui(vertices, &region, position.x as f64, position.y as f64, z, 1.0);
         }
 
         // transform
-        let (pixel_width, pixel_height) = self.dimensions.pixels;
+        let viewport = self.viewport();
+        let viewport_size = viewport.size();
+        let (pixel_width, pixel_height) = (viewport_size.x, viewport_size.y);
 
 //        println!("screen dimensions {:?} {:?}", pixel_width, pixel_height);
         let transform = ui_projection(pixel_width as f64, pixel_height as f64);
@@ -430,7 +915,6 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
             transform : down_size_m4(transform.into()),
             color: color::WHITE,
         };
-        let geo = self.upload(&vertices);
 
         let mut reclaim_elements : Vec<ElementWithSize<i32>> = Vec::new();
 
@@ -451,8 +935,20 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
             }
         }
 
+        for (clip, vertices) in batches {
+            let geo = self.upload(&vertices);
+            if let Some(rect) = clip {
+                // view clips are relative to the viewport
+                self.push_clip(rect.offset(viewport.min));
+            }
+            let res = self.draw_raw(&geo, uniforms, Blend::Alpha, TextureArraySource::UI);
+            if clip.is_some() {
+                self.pop_clip();
+            }
+            res?;
+        }
 
-        self.draw_raw(&geo, uniforms, Blend::Alpha, TextureArraySource::UI)
+        Ok(())
     }
 
     //        for l in 0..8 {
@@ -462,7 +958,8 @@ impl<F> Renderer<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer, F, gfx_
     //                v_min: 0,
     //                v_max: 512,
     //                layer: l,
-    //                texture_size: 512,
+    //                texture_width: 512,
+    //                texture_height: 512,
     //            };
     //            let scale = 0.10;
     //
@@ -477,18 +974,33 @@ pub fn texture_kind_for(dimensions: &TextureArrayDimensions) -> gfx::texture::Ki
     gfx::texture::Kind::D2Array(dimensions.width as u16, dimensions.height as u16, dimensions.layers as u16, gfx::texture::AaMode::Single)
 }
 
-pub fn check_reload(rx: &Receiver<RawEvent>, files:&FileResources) -> (bool, bool) {
-    let mut reload_program = false;
-    let mut reload_texture = false;
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reload {
+    pub program: bool,
+    pub textures: Vec<PathBuf>, // changed paths inside the texture directory
+    pub changed: Vec<PathBuf>, // every changed path, for game side data like maps
+}
+
+pub fn check_reload(rx: &Receiver<RawEvent>, files:&FileResources) -> Reload {
+    let mut reload = Reload {
+        program: false,
+        textures: Vec::new(),
+        changed: Vec::new(),
+    };
 
     'fs: loop {
         match rx.try_recv() {
             Ok(RawEvent { path, op:_, cookie:_ }) => {
                 if let Some(p) = path {
-                    if files.shader_pair.contains(&p) {
-                        reload_program = true;
+                    if !reload.changed.contains(&p) {
+                        reload.changed.push(p.clone());
+                    }
+                    if files.shader_pair.contains(&p) || files.compact_shader_pair.as_ref().map(|c| c.contains(&p)).unwrap_or(false) {
+                        reload.program = true;
                     } else if files.texture_directory.contains(&p) {
-                        reload_texture = true;
+                        if !reload.textures.contains(&p) {
+                            reload.textures.push(p);
+                        }
                     } else {
                         use std::path;
                         let components: Vec<path::Component> = p.components().collect();
@@ -500,5 +1012,27 @@ pub fn check_reload(rx: &Receiver<RawEvent>, files:&FileResources) -> (bool, boo
         }
     }
 
-    (reload_program, reload_texture)
-}
\ No newline at end of file
+    reload
+}
+
+pub fn upload_texture_layer<R, C>(encoder: &mut gfx::Encoder<R, C>, texture: &gfx::handle::Texture<R, R8_G8_B8_A8>, layer: u32, img: &RgbaImage) -> JamResult<()> where R : gfx::Resources, C : gfx::CommandBuffer<R> {
+    // textures are bottom up
+    let flipped = DynamicImage::ImageRgba8(img.clone()).flipv().to_rgba();
+    let image_info = ImageInfoCommon {
+        xoffset: 0,
+        yoffset: 0,
+        zoffset: layer as u16,
+        width: flipped.width() as u16,
+        height: flipped.height() as u16,
+        depth: 1,
+        format: (),
+        mipmap: 0,
+    };
+    let data : Vec<[u8; 4]> = flipped.into_raw().chunks(4).map(|sl| [sl[0], sl[1], sl[2], sl[3]]).collect();
+    encoder.update_texture::<R8_G8_B8_A8, Srgba8>(
+        texture,
+        None,
+        image_info,
+        &data,
+    ).map_err(JamError::TextureUpdateError)
+}
@@ -0,0 +1,142 @@
+use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::path::PathBuf;
+
+use image::RgbaImage;
+
+use {JamResult, JamError, OurFont};
+use font::load_fonts_in_path;
+use render::texture_array::{TextureDirectory, TextureArrayData, load_image};
+
+// shared between the worker thread and whoever wants to draw a loading bar
+#[derive(Debug)]
+pub struct LoadProgress {
+    pub loaded: AtomicUsize,
+    pub total: AtomicUsize,
+}
+
+impl LoadProgress {
+    pub fn new() -> LoadProgress {
+        LoadProgress {
+            loaded: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::SeqCst);
+    }
+
+    pub fn increment(&self) {
+        self.loaded.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn status(&self) -> LoadStatus {
+        LoadStatus {
+            loaded: self.loaded.load(Ordering::SeqCst),
+            total: self.total.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LoadStatus {
+    pub loaded: usize,
+    pub total: usize,
+}
+
+impl LoadStatus {
+    pub fn none() -> LoadStatus {
+        LoadStatus {
+            loaded: 0,
+            total: 0,
+        }
+    }
+
+    pub fn combine(&self, other: LoadStatus) -> LoadStatus {
+        LoadStatus {
+            loaded: self.loaded + other.loaded,
+            total: self.total + other.total,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.loaded >= self.total
+    }
+
+    // 0.0 -> 1.0, complete when there's nothing to load
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.loaded as f64) / (self.total as f64)
+        }
+    }
+}
+
+// the result of some decoding happening on a worker thread
+pub struct PendingLoad<T> {
+    pub receiver: Receiver<JamResult<T>>,
+    pub progress: Arc<LoadProgress>,
+}
+
+impl<T> PendingLoad<T> where T : Send + 'static {
+    pub fn spawn<F>(work: F) -> PendingLoad<T> where F : FnOnce(&LoadProgress) -> JamResult<T> + Send + 'static {
+        let (tx, rx) = channel();
+        let progress = Arc::new(LoadProgress::new());
+        let thread_progress = progress.clone();
+
+        thread::spawn(move || {
+            let result = work(&thread_progress);
+            // nobody listening means the load was superseded, that's fine
+            let _ = tx.send(result);
+        });
+
+        PendingLoad {
+            receiver: rx,
+            progress: progress,
+        }
+    }
+
+    // None while still working
+    pub fn poll(&self) -> Option<JamResult<T>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(JamError::LoaderDisconnected)),
+        }
+    }
+
+    pub fn status(&self) -> LoadStatus {
+        self.progress.status()
+    }
+}
+
+pub fn spawn_texture_load(directory: &TextureDirectory) -> PendingLoad<TextureArrayData> {
+    let directory = directory.clone();
+    PendingLoad::spawn(move |progress| directory.load_reporting(progress))
+}
+
+// (layer, path) pairs, decoded in to (layer, image) pairs
+pub fn spawn_texture_layer_load(layers: Vec<(u32, PathBuf)>) -> PendingLoad<Vec<(u32, RgbaImage)>> {
+    PendingLoad::spawn(move |progress| {
+        progress.set_total(layers.len());
+        let mut images = Vec::new();
+        for (layer, path) in layers {
+            images.push((layer, load_image(&path)?));
+            progress.increment();
+        }
+        Ok(images)
+    })
+}
+
+pub fn spawn_font_load(path: PathBuf) -> PendingLoad<Vec<OurFont>> {
+    PendingLoad::spawn(move |progress| {
+        progress.set_total(1);
+        let fonts = load_fonts_in_path(path.as_path())?;
+        progress.increment();
+        Ok(fonts)
+    })
+}
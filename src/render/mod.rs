@@ -2,21 +2,59 @@
 
 pub mod gfx;
 
+pub mod animation;
+pub mod aseprite;
+pub mod autotile;
+pub mod billboard;
+pub mod block;
 pub mod command;
+pub mod compact;
+pub mod debug;
+pub mod loader;
+pub mod named_regions;
+pub mod obj;
+pub mod packer;
+pub mod parallel;
+pub mod particles;
 pub mod quads;
 pub mod shader;
+pub mod shapes;
+pub mod slice;
+pub mod sprite_sheet;
 pub mod text;
 pub mod texture_array;
+pub mod tiled;
+pub mod tilemap;
+pub mod trail;
 pub mod texture_region;
 
+pub use self::animation::*;
+pub use self::aseprite::*;
+pub use self::autotile::*;
+pub use self::billboard::*;
+pub use self::block::*;
 pub use self::command::*;
+pub use self::compact::*;
+pub use self::debug::*;
+pub use self::loader::*;
+pub use self::named_regions::*;
+pub use self::obj::*;
+pub use self::packer::*;
+pub use self::parallel::*;
+pub use self::particles::*;
 pub use self::quads::*;
 pub use self::shader::*;
+pub use self::shapes::*;
+pub use self::slice::*;
+pub use self::sprite_sheet::*;
 pub use self::text::*;
 pub use self::texture_array::*;
+pub use self::tiled::*;
+pub use self::tilemap::*;
+pub use self::trail::*;
 pub use self::texture_region::*;
 
-pub use self::gfx::Vertex;
+pub use self::gfx::{Vertex, CompactVertex};
 
 use FontDirectory;
 use notify::{RecommendedWatcher, PollWatcher, Watcher, RecursiveMode, RawEvent, FsEventWatcher};
@@ -34,9 +72,16 @@ pub fn down_size_m4(arr: [[f64; 4];4]) -> [[f32; 4]; 4] {
     out
 }
 
+// whether any of a file's sources are in renderer.changed_paths(), which should be passed each frame
+// watched paths are absolute and sources usually relative, so either may end with the other
+pub fn any_changed(changed: &[PathBuf], sources: &[PathBuf]) -> bool {
+    changed.iter().any(|c| sources.iter().any(|s| c.ends_with(s) || s.ends_with(c)))
+}
+
 pub struct FileResources {
     pub resources: PathBuf,
     pub shader_pair : ShaderPair,
+    pub compact_shader_pair : Option<ShaderPair>, // for CompactVertex geometry, None leaves draw_compact without a pipeline
     pub texture_directory: TextureDirectory,
     pub font_directory: FontDirectory,
 }
@@ -47,6 +92,25 @@ pub struct FileWatcher {
 }
 
 impl FileResources {
+    // without a compact shader, see with_compact_shader_pair
+    pub fn new(resources: PathBuf, shader_pair: ShaderPair, texture_directory: TextureDirectory, font_directory: FontDirectory) -> FileResources {
+        FileResources {
+            resources: resources,
+            shader_pair: shader_pair,
+            compact_shader_pair: None,
+            texture_directory: texture_directory,
+            font_directory: font_directory,
+        }
+    }
+
+    // needed for Renderer::draw_compact
+    pub fn with_compact_shader_pair(self, compact_shader_pair: ShaderPair) -> FileResources {
+        FileResources {
+            compact_shader_pair: Some(compact_shader_pair),
+            .. self
+        }
+    }
+
     pub fn watch(&self) -> FileWatcher {
         let (tx, notifier_rx) = channel::<RawEvent>();
 
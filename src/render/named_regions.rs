@@ -0,0 +1,110 @@
+use std::path::{Path, Component};
+
+use aphid::HashMap;
+
+use {JamResult, JamError};
+use render::texture_region::TextureRegion;
+
+// regions of a loaded texture array by name, names are the path relative to the texture directory
+// without extension, using '/' between directories e.g. "characters/hero"
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedRegions {
+    pub regions: HashMap<String, TextureRegion>,
+}
+
+impl NamedRegions {
+    pub fn new(regions: HashMap<String, TextureRegion>) -> NamedRegions {
+        NamedRegions {
+            regions: regions,
+        }
+    }
+
+    pub fn empty() -> NamedRegions {
+        NamedRegions::new(HashMap::default())
+    }
+
+    pub fn get(&self, name: &str) -> Option<TextureRegion> {
+        self.regions.get(name).cloned()
+    }
+
+    pub fn region(&self, name: &str) -> JamResult<TextureRegion> {
+        self.get(name).ok_or_else(|| JamError::UnknownTexture {
+            name: name.to_string(),
+            similar: self.similar_names(name),
+        })
+    }
+
+    pub fn layer(&self, name: &str) -> JamResult<u32> {
+        self.region(name).map(|r| r.layer)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.regions.contains_key(name)
+    }
+
+    // sorted, so it's stable between runs
+    pub fn names(&self) -> Vec<String> {
+        let mut names : Vec<String> = self.regions.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn insert(&mut self, name: String, region: TextureRegion) {
+        self.regions.insert(name, region);
+    }
+
+    // a few candidates for error messages, typos usually share the last path component or a prefix
+    fn similar_names(&self, name: &str) -> Vec<String> {
+        let lowered = name.to_lowercase();
+        let stem = lowered.rsplit('/').next().unwrap_or("").to_string();
+
+        self.names().into_iter().filter(|n| {
+            let n_lower = n.to_lowercase();
+            let n_stem = n_lower.rsplit('/').next().unwrap_or("").to_string();
+            n_lower.contains(&lowered) || lowered.contains(&n_lower) || (!stem.is_empty() && (n_stem.contains(&stem) || stem.contains(&n_stem)))
+        }).take(5).collect()
+    }
+}
+
+// "resources/textures/characters/hero.png" relative to "resources/textures" -> "characters/hero"
+pub fn sprite_name(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let without_extension = relative.with_extension("");
+
+    let parts : Vec<String> = without_extension.components().filter_map(|c| {
+        match c {
+            Component::Normal(part) => part.to_str().map(|s| s.to_string()),
+            _ => None,
+        }
+    }).collect();
+
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn names_from_paths() {
+        let root = PathBuf::from("resources/textures");
+        assert_eq!(sprite_name(&root, &root.join("hero.png")), "hero");
+        assert_eq!(sprite_name(&root, &root.join("characters").join("hero.png")), "characters/hero");
+    }
+
+    #[test]
+    fn missing_names_are_descriptive() {
+        let mut named = NamedRegions::empty();
+        named.insert("characters/hero".into(), TextureRegion { u_min: 0, u_max: 16, v_min: 0, v_max: 16, layer: 3, texture_width: 16, texture_height: 16 });
+
+        assert_eq!(named.layer("characters/hero").expect("a layer"), 3);
+        match named.region("hero") {
+            Err(JamError::UnknownTexture { name, similar }) => {
+                assert_eq!(name, "hero");
+                assert_eq!(similar, vec!["characters/hero".to_string()]);
+            },
+            other => panic!("expected an unknown texture error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+
+use cgmath::InnerSpace;
+use aphid::HashMap;
+
+use {JamResult, JamError, Vec3, load_file_contents};
+use render::Vertex;
+use render::texture_region::TextureRegion;
+use render::named_regions::{NamedRegions, sprite_name};
+use render::any_changed;
+
+pub const OBJ_EXTENSION : &'static str = "obj";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub diffuse: [f32; 3], // Kd
+    pub alpha: f32, // d, or 1 - Tr
+    pub texture: Option<PathBuf>, // map_Kd resolved against the mtl
+}
+
+impl ObjMaterial {
+    pub fn new(name: &str) -> ObjMaterial {
+        ObjMaterial {
+            name: name.to_string(),
+            diffuse: [1.0, 1.0, 1.0],
+            alpha: 1.0,
+            texture: None,
+        }
+    }
+}
+
+// indices in to the model's positions, uvs and normals
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ObjCorner {
+    pub position: usize,
+    pub uv: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+// faces are triangulated as they're read
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjTriangles {
+    pub material: Option<String>,
+    pub triangles: Vec<[ObjCorner; 3]>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjModel {
+    pub positions: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub normals: Vec<Vec3>,
+    pub groups: Vec<ObjTriangles>, // one per usemtl run
+    pub materials: HashMap<String, ObjMaterial>,
+    pub material_libraries: Vec<PathBuf>, // resolved against the obj
+}
+
+impl ObjModel {
+    pub fn triangle_count(&self) -> usize {
+        self.groups.iter().map(|g| g.triangles.len()).sum()
+    }
+
+    // material textures must live in the texture directory, they're looked up by sprite name
+    // untextured materials sample the untextured region (e.g. a white texel), uvs outside 0..1 won't wrap
+    pub fn vertices(&self, texture_root: &Path, images: &NamedRegions, untextured: &TextureRegion) -> JamResult<Vec<Vertex>> {
+        let mut vertices = Vec::with_capacity(self.triangle_count() * 3);
+
+        for group in &self.groups {
+            let material = group.material.as_ref().and_then(|m| self.materials.get(m));
+            let region = match material.and_then(|m| m.texture.as_ref()) {
+                Some(texture) => images.region(&sprite_name(texture_root, texture))?,
+                None => *untextured,
+            };
+            let color = match material {
+                Some(m) => [m.diffuse[0], m.diffuse[1], m.diffuse[2], m.alpha],
+                None => [1.0, 1.0, 1.0, 1.0],
+            };
+            let layer_f = region.layer as f32;
+
+            for triangle in &group.triangles {
+                let p = |c: &ObjCorner| self.positions[c.position];
+                let face_normal = (p(&triangle[1]) - p(&triangle[0])).cross(p(&triangle[2]) - p(&triangle[0]));
+                let face_normal = if face_normal.magnitude2() > 0.0 { face_normal.normalize() } else { face_normal };
+
+                for corner in triangle.iter() {
+                    let position = p(corner);
+                    let normal = corner.normal.map(|n| self.normals[n]).unwrap_or(face_normal);
+                    // obj v counts up from the bottom, as our regions do
+                    let (u, v) = corner.uv.map(|t| self.uvs[t]).unwrap_or((0.0, 0.0));
+                    vertices.push(Vertex {
+                        position: [position.x as f32, position.y as f32, position.z as f32],
+                        tex_coord: [region.nu_min() + (u as f32) * region.n_width(), region.nv_min() + (v as f32) * region.n_height(), layer_f],
+                        color: color,
+                        normal: [normal.x as f32, normal.y as f32, normal.z as f32],
+                    });
+                }
+            }
+        }
+
+        Ok(vertices)
+    }
+}
+
+pub fn is_obj(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase() == OBJ_EXTENSION).unwrap_or(false)
+}
+
+// loads the obj and every mtl it references
+pub fn load_obj(path: &Path) -> JamResult<ObjModel> {
+    let bytes = load_file_contents(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut model = parse_obj(&text, dir).map_err(|e| JamError::ObjError(path.to_path_buf(), e))?;
+
+    for library in model.material_libraries.clone() {
+        let bytes = load_file_contents(&library)?;
+        let text = String::from_utf8_lossy(&bytes);
+        let library_dir = library.parent().unwrap_or(Path::new(""));
+        let materials = parse_mtl(&text, library_dir).map_err(|e| JamError::ObjError(library.clone(), e))?;
+        for material in materials {
+            model.materials.insert(material.name.clone(), material);
+        }
+    }
+
+    Ok(model)
+}
+
+fn floats(parts: SplitWhitespace, line: usize) -> Result<Vec<f64>, String> {
+    parts.map(|p| p.parse::<f64>().map_err(|e| format!("line {} bad number {:?} -> {:?}", line, p, e))).collect()
+}
+
+// 1 based, negatives count back from the end
+fn resolve_index(raw: &str, count: usize, line: usize) -> Result<usize, String> {
+    let index = raw.parse::<i64>().map_err(|e| format!("line {} bad index {:?} -> {:?}", line, raw, e))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("line {} index {} out of range", line, index));
+    }
+    Ok(resolved as usize)
+}
+
+// material libraries are resolved against dir but not loaded
+pub fn parse_obj(text: &str, dir: &Path) -> Result<ObjModel, String> {
+    let mut model = ObjModel {
+        positions: Vec::new(),
+        uvs: Vec::new(),
+        normals: Vec::new(),
+        groups: Vec::new(),
+        materials: HashMap::default(),
+        material_libraries: Vec::new(),
+    };
+    let mut current = ObjTriangles { material: None, triangles: Vec::new() };
+
+    for (i, raw_line) in text.lines().enumerate() {
+        let line = i + 1;
+        let content = raw_line.split('#').next().unwrap_or("").trim();
+        let mut parts = content.split_whitespace();
+        let keyword = match parts.next() {
+            Some(k) => k,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => {
+                let v = floats(parts, line)?;
+                if v.len() < 3 {
+                    return Err(format!("line {} vertex needs 3 coordinates", line));
+                }
+                model.positions.push(Vec3::new(v[0], v[1], v[2]));
+            },
+            "vt" => {
+                let t = floats(parts, line)?;
+                if t.is_empty() {
+                    return Err(format!("line {} uv needs a coordinate", line));
+                }
+                model.uvs.push((t[0], t.get(1).cloned().unwrap_or(0.0)));
+            },
+            "vn" => {
+                let n = floats(parts, line)?;
+                if n.len() < 3 {
+                    return Err(format!("line {} normal needs 3 components", line));
+                }
+                let normal = Vec3::new(n[0], n[1], n[2]);
+                model.normals.push(if normal.magnitude2() > 0.0 { normal.normalize() } else { normal });
+            },
+            "f" => {
+                let mut corners = Vec::new();
+                for corner in parts {
+                    let mut indices = corner.split('/');
+                    let position = resolve_index(indices.next().unwrap_or(""), model.positions.len(), line)?;
+                    let uv = match indices.next() {
+                        Some(t) if !t.is_empty() => Some(resolve_index(t, model.uvs.len(), line)?),
+                        _ => None,
+                    };
+                    let normal = match indices.next() {
+                        Some(n) if !n.is_empty() => Some(resolve_index(n, model.normals.len(), line)?),
+                        _ => None,
+                    };
+                    corners.push(ObjCorner { position: position, uv: uv, normal: normal });
+                }
+                if corners.len() < 3 {
+                    return Err(format!("line {} face needs at least 3 corners", line));
+                }
+                // fan, fine for the convex faces exporters write
+                for n in 1..(corners.len() - 1) {
+                    current.triangles.push([corners[0], corners[n], corners[n + 1]]);
+                }
+            },
+            "usemtl" => {
+                let material = parts.next().map(|m| m.to_string());
+                if current.material != material {
+                    if !current.triangles.is_empty() {
+                        model.groups.push(current);
+                    }
+                    current = ObjTriangles { material: material, triangles: Vec::new() };
+                }
+            },
+            "mtllib" => {
+                // several libraries may share a line
+                for library in parts {
+                    model.material_libraries.push(dir.join(library));
+                }
+            },
+            _ => (), // objects, groups, smoothing and curves don't change the triangles
+        }
+    }
+
+    if !current.triangles.is_empty() {
+        model.groups.push(current);
+    }
+
+    Ok(model)
+}
+
+pub fn parse_mtl(text: &str, dir: &Path) -> Result<Vec<ObjMaterial>, String> {
+    let mut materials : Vec<ObjMaterial> = Vec::new();
+
+    for (i, raw_line) in text.lines().enumerate() {
+        let line = i + 1;
+        let content = raw_line.split('#').next().unwrap_or("").trim();
+        let mut parts = content.split_whitespace();
+        let keyword = match parts.next() {
+            Some(k) => k,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = parts.next().ok_or_else(|| format!("line {} newmtl without a name", line))?;
+            materials.push(ObjMaterial::new(name));
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(m) => m,
+            None => continue,
+        };
+
+        match keyword {
+            "Kd" => {
+                let kd = floats(parts, line)?;
+                if kd.len() < 3 {
+                    return Err(format!("line {} Kd needs 3 components", line));
+                }
+                material.diffuse = [kd[0] as f32, kd[1] as f32, kd[2] as f32];
+            },
+            "d" => material.alpha = floats(parts, line)?.get(0).cloned().unwrap_or(1.0) as f32,
+            "Tr" => material.alpha = 1.0 - floats(parts, line)?.get(0).cloned().unwrap_or(0.0) as f32,
+            "map_Kd" => {
+                // options like -bm come first, the file is last
+                if let Some(file) = parts.last() {
+                    material.texture = Some(dir.join(file));
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Ok(materials)
+}
+
+// a loaded model that reloads itself when the renderer reports its files changed
+pub struct ObjFile {
+    pub path: PathBuf,
+    pub model: ObjModel,
+}
+
+impl ObjFile {
+    pub fn load(path: &Path) -> JamResult<ObjFile> {
+        Ok(ObjFile {
+            path: path.to_path_buf(),
+            model: load_obj(path)?,
+        })
+    }
+
+    // the obj and its material libraries
+    pub fn source_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.path.clone()];
+        paths.extend(self.model.material_libraries.iter().cloned());
+        paths
+    }
+
+    // true when the model was reloaded and needs uploading again, a failed reload keeps the previous model
+    pub fn reload_if_changed(&mut self, changed: &[PathBuf]) -> JamResult<bool> {
+        let affected = any_changed(changed, &self.source_paths());
+        if affected {
+            self.model = load_obj(&self.path)?;
+        }
+        Ok(affected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quads_are_triangulated_per_material() {
+        let text = "mtllib crate.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\nusemtl wood\nf 1/1/1 2/2/1 3/3/1 4/4/1\nusemtl metal\nf -4//1 -2//1 -1//1\n";
+        let model = parse_obj(text, Path::new("models")).unwrap();
+        assert_eq!(model.material_libraries, vec![PathBuf::from("models/crate.mtl")]);
+        assert_eq!(model.groups.len(), 2);
+        assert_eq!(model.groups[0].triangles.len(), 2);
+        assert_eq!(model.groups[1].triangles[0][0], ObjCorner { position: 0, uv: None, normal: Some(0) });
+
+        let mut model = model;
+        for material in parse_mtl("newmtl wood\nKd 1 0.5 0\nmap_Kd -bm 1 wood.png\nnewmtl metal\nKd 0.5 0.5 0.5\nd 0.5\n", Path::new("textures")).unwrap() {
+            model.materials.insert(material.name.clone(), material);
+        }
+
+        let mut images = NamedRegions::empty();
+        images.insert("wood".into(), TextureRegion { u_min: 32, u_max: 64, v_min: 0, v_max: 32, layer: 3, texture_width: 64, texture_height: 64 });
+        let white = TextureRegion { u_min: 1, u_max: 3, v_min: 1, v_max: 3, layer: 0, texture_width: 64, texture_height: 64 };
+
+        let vertices = model.vertices(Path::new("textures"), &images, &white).unwrap();
+        assert_eq!(vertices.len(), 9);
+        // uv (1, 1) lands on the far corner of the wood region
+        assert_eq!(vertices[2].tex_coord, [1.0, 0.5, 3.0]);
+        assert_eq!(vertices[2].color, [1.0, 0.5, 0.0, 1.0]);
+        assert_eq!(vertices[6].tex_coord[2], 0.0);
+        assert_eq!(vertices[6].color[3], 0.5);
+    }
+
+    #[test]
+    fn several_libraries_per_line_and_zero_normals() {
+        let model = parse_obj("mtllib a.mtl b.mtl\nvn 0 0 0\nvn 0 2 0\n", Path::new("models")).unwrap();
+        assert_eq!(model.material_libraries, vec![PathBuf::from("models/a.mtl"), PathBuf::from("models/b.mtl")]);
+        assert_eq!(model.normals, vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)]);
+    }
+
+    #[test]
+    fn reloads_when_a_material_library_changes() {
+        use test_support::TempDir;
+
+        let dir = TempDir::new("obj_reload");
+        let obj = dir.write("crate.obj", b"mtllib crate.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nusemtl wood\nf 1 2 3\n");
+        let mtl = dir.write("crate.mtl", b"newmtl wood\nKd 1 0 0\n");
+
+        let mut file = ObjFile::load(&obj).unwrap();
+        assert_eq!(file.model.materials["wood"].diffuse, [1.0, 0.0, 0.0]);
+        assert!(!file.reload_if_changed(&[dir.path().join("other.png")]).unwrap());
+
+        dir.write("crate.mtl", b"newmtl wood\nKd 0 1 0\n");
+        assert!(file.reload_if_changed(&[mtl.clone()]).unwrap());
+        assert_eq!(file.model.materials["wood"].diffuse, [0.0, 1.0, 0.0]);
+
+        // a broken save keeps what was there
+        dir.write("crate.obj", b"vn 1\n");
+        assert!(file.reload_if_changed(&[obj.clone()]).is_err());
+        assert_eq!(file.model.triangle_count(), 1);
+    }
+}
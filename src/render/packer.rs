@@ -0,0 +1,266 @@
+use image::{RgbaImage, Rgba};
+use aphid::HashMap;
+
+use {JamResult, JamError};
+use render::texture_region::TextureRegion;
+use render::texture_array::{TextureArrayData, TextureArrayDimensions};
+use render::named_regions::NamedRegions;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PackerConfig {
+    pub layer_width: u32,
+    pub layer_height: u32,
+    pub padding: u32, // transparent gap between neighbouring images
+    pub extrude: u32, // edge pixels repeated outward, stops filtering bleeding in neighbours/transparency
+    pub max_layers: u32,
+}
+
+impl PackerConfig {
+    pub fn new(layer_width: u32, layer_height: u32) -> PackerConfig {
+        PackerConfig {
+            layer_width: layer_width,
+            layer_height: layer_height,
+            padding: 2,
+            extrude: 1,
+            max_layers: 64,
+        }
+    }
+
+    pub fn square(layer_size: u32) -> PackerConfig {
+        PackerConfig::new(layer_size, layer_size)
+    }
+}
+
+// where an image ended up, x/y are the top left of the image itself (inside any extrusion)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Placement {
+    pub layer: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    x_used: u32,
+}
+
+struct PackingLayer {
+    shelves: Vec<Shelf>,
+    y_used: u32,
+}
+
+impl PackingLayer {
+    fn new(padding: u32) -> PackingLayer {
+        PackingLayer {
+            shelves: Vec::new(),
+            y_used: padding,
+        }
+    }
+
+    // cell sizes include extrusion and trailing padding, returns the top left of the cell
+    fn insert(&mut self, cell_width: u32, cell_height: u32, config: &PackerConfig) -> Option<(u32, u32)> {
+        for shelf in self.shelves.iter_mut() {
+            if cell_height <= shelf.height && shelf.x_used + cell_width <= config.layer_width {
+                let at = (shelf.x_used, shelf.y);
+                shelf.x_used += cell_width;
+                return Some(at);
+            }
+        }
+
+        if self.y_used + cell_height <= config.layer_height && config.padding + cell_width <= config.layer_width {
+            let shelf = Shelf {
+                y: self.y_used,
+                height: cell_height,
+                x_used: config.padding + cell_width,
+            };
+            let at = (config.padding, shelf.y);
+            self.y_used += cell_height;
+            self.shelves.push(shelf);
+            Some(at)
+        } else {
+            None
+        }
+    }
+}
+
+// shelf packing, tallest first, the output is deterministic for a given set of names/sizes
+pub fn pack_sizes(sizes: &[(String, u32, u32)], config: &PackerConfig) -> JamResult<(Vec<Placement>, u32)> {
+    let mut order : Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (ref an, aw, ah) = sizes[a];
+        let (ref bn, bw, bh) = sizes[b];
+        bh.cmp(&ah).then(bw.cmp(&aw)).then(an.cmp(bn))
+    });
+
+    let border = config.extrude * 2 + config.padding;
+
+    let mut layers : Vec<PackingLayer> = Vec::new();
+    let mut placements : Vec<Option<Placement>> = vec![None; sizes.len()];
+
+    for i in order {
+        let (ref name, width, height) = sizes[i];
+        let cell_width = width + border;
+        let cell_height = height + border;
+
+        if cell_width + config.padding > config.layer_width || cell_height + config.padding > config.layer_height {
+            return Err(JamError::ImageTooLargeToPack(name.clone()));
+        }
+
+        let mut placed = None;
+        for (layer_idx, layer) in layers.iter_mut().enumerate() {
+            if let Some(at) = layer.insert(cell_width, cell_height, config) {
+                placed = Some((layer_idx as u32, at));
+                break;
+            }
+        }
+
+        let (layer, (cx, cy)) = match placed {
+            Some(p) => p,
+            None => {
+                if layers.len() as u32 >= config.max_layers {
+                    return Err(JamError::TooManyLayers);
+                }
+                let mut layer = PackingLayer::new(config.padding);
+                let at = layer.insert(cell_width, cell_height, config).expect("an empty layer fits any checked image");
+                layers.push(layer);
+                ((layers.len() - 1) as u32, at)
+            },
+        };
+
+        placements[i] = Some(Placement {
+            layer: layer,
+            x: cx + config.extrude,
+            y: cy + config.extrude,
+            width: width,
+            height: height,
+        });
+    }
+
+    Ok((placements.into_iter().map(|p| p.expect("every image placed")).collect(), layers.len() as u32))
+}
+
+// copies the image in to place, repeating its edge pixels outward by extrude
+pub fn blit_extruded(target: &mut RgbaImage, source: &RgbaImage, x: u32, y: u32, extrude: u32) {
+    let (width, height) = source.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let e = extrude as i64;
+    for ty in (y as i64 - e)..(y as i64 + height as i64 + e) {
+        for tx in (x as i64 - e)..(x as i64 + width as i64 + e) {
+            if tx < 0 || ty < 0 || tx >= target.width() as i64 || ty >= target.height() as i64 {
+                continue;
+            }
+            let sx = clamp_i64(tx - x as i64, 0, width as i64 - 1) as u32;
+            let sy = clamp_i64(ty - y as i64, 0, height as i64 - 1) as u32;
+            target.put_pixel(tx as u32, ty as u32, *source.get_pixel(sx, sy));
+        }
+    }
+}
+
+fn clamp_i64(n: i64, min: i64, max: i64) -> i64 {
+    if n < min { min } else if n > max { max } else { n }
+}
+
+// regions follow the texture convention of v counting up from the bottom of the layer
+pub fn region_for(placement: &Placement, layer_width: u32, layer_height: u32) -> TextureRegion {
+    TextureRegion {
+        u_min: placement.x,
+        u_max: placement.x + placement.width,
+        v_min: layer_height - (placement.y + placement.height),
+        v_max: layer_height - placement.y,
+        layer: placement.layer,
+        texture_width: layer_width,
+        texture_height: layer_height,
+    }
+}
+
+// the regions of the result are keyed by the given names
+pub fn pack_images(images: Vec<(String, RgbaImage)>, config: &PackerConfig) -> JamResult<TextureArrayData> {
+    if images.is_empty() {
+        return Err(JamError::NoFiles);
+    }
+
+    let sizes : Vec<(String, u32, u32)> = images.iter().map(|&(ref name, ref img)| (name.clone(), img.width(), img.height())).collect();
+    let (placements, layer_count) = pack_sizes(&sizes, config)?;
+
+    let mut layers : Vec<RgbaImage> = (0..layer_count).map(|_| {
+        RgbaImage::from_pixel(config.layer_width, config.layer_height, Rgba { data: [0, 0, 0, 0] })
+    }).collect();
+
+    let mut regions = NamedRegions::empty();
+
+    for (&(ref name, ref img), placement) in images.iter().zip(placements.iter()) {
+        blit_extruded(&mut layers[placement.layer as usize], img, placement.x, placement.y, config.extrude);
+        regions.insert(name.clone(), region_for(placement, config.layer_width, config.layer_height));
+    }
+
+    Ok(TextureArrayData {
+        dimensions: TextureArrayDimensions {
+            width: config.layer_width,
+            height: config.layer_height,
+            layers: layer_count,
+        },
+        images: layers,
+        paths: Vec::new(), // layers don't map to single files
+        regions: regions,
+        image_regions: NamedRegions::empty(), // filled in by the texture directory
+        sheets: HashMap::default(),
+        animations: HashMap::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: &Placement, b: &Placement) -> bool {
+        a.layer == b.layer &&
+            a.x < b.x + b.width && b.x < a.x + a.width &&
+            a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn packs_without_overlap() {
+        let sizes : Vec<(String, u32, u32)> = (0..40).map(|i| (format!("img_{}", i), 8 + (i * 7) % 50, 8 + (i * 13) % 40)).collect();
+        let config = PackerConfig::new(256, 128);
+        let (placements, _) = pack_sizes(&sizes, &config).expect("a packing");
+
+        for (i, a) in placements.iter().enumerate() {
+            assert!(a.x + a.width + config.extrude <= config.layer_width);
+            assert!(a.y + a.height + config.extrude <= config.layer_height);
+            for b in placements.iter().skip(i + 1) {
+                assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn overflows_in_to_new_layers() {
+        let sizes : Vec<(String, u32, u32)> = (0..5).map(|i| (format!("img_{}", i), 100, 100)).collect();
+        let (placements, layers) = pack_sizes(&sizes, &PackerConfig::square(128)).expect("a packing");
+        assert_eq!(layers, 5);
+        assert_eq!(placements[4].layer, 4);
+    }
+
+    #[test]
+    fn rejects_oversized() {
+        let sizes = vec![("huge".to_string(), 300, 10)];
+        assert!(pack_sizes(&sizes, &PackerConfig::square(256)).is_err());
+    }
+
+    #[test]
+    fn extrudes_edges() {
+        let red = Rgba { data: [255, 0, 0, 255] };
+        let source = RgbaImage::from_pixel(2, 2, red);
+        let mut target = RgbaImage::from_pixel(8, 8, Rgba { data: [0, 0, 0, 0] });
+        blit_extruded(&mut target, &source, 3, 3, 1);
+        assert_eq!(*target.get_pixel(2, 2), red);
+        assert_eq!(*target.get_pixel(5, 5), red);
+        assert_eq!(target.get_pixel(1, 1).data[3], 0);
+    }
+}
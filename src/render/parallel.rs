@@ -0,0 +1,81 @@
+use std::thread;
+use std::sync::Arc;
+
+use {JamResult, JamError};
+use render::Vertex;
+
+// tesselates each job on one of up to workers threads, results come back in job order whatever order the
+// threads finish in, so the output is the same as tesselating serially
+// jobs are split in to contiguous runs, one per thread, so keep them roughly the same size (e.g. a chunk each)
+pub fn tesselate_parallel<T, F>(jobs: Vec<T>, workers: usize, tesselate: F) -> JamResult<Vec<Vec<Vertex>>> where T : Send + 'static, F : Fn(&T) -> Vec<Vertex> + Send + Sync + 'static {
+    let workers = workers.max(1).min(jobs.len().max(1));
+    if workers == 1 {
+        return Ok(jobs.iter().map(|j| tesselate(j)).collect());
+    }
+
+    let per_worker = (jobs.len() + workers - 1) / workers;
+    let tesselate = Arc::new(tesselate);
+
+    let mut runs : Vec<Vec<T>> = Vec::with_capacity(workers);
+    let mut remaining = jobs.into_iter();
+    for _ in 0..workers {
+        runs.push(remaining.by_ref().take(per_worker).collect());
+    }
+
+    let handles : Vec<thread::JoinHandle<Vec<Vec<Vertex>>>> = runs.into_iter().enumerate().map(|(i, run)| {
+        let tesselate = tesselate.clone();
+        thread::Builder::new().name(format!("tesselate-{}", i)).spawn(move || {
+            run.iter().map(|j| tesselate(j)).collect()
+        })
+    }).collect::<Result<Vec<_>, _>>()?;
+
+    // joined in order, which is what makes the result deterministic
+    let mut results = Vec::new();
+    for handle in handles {
+        let run = handle.join().map_err(|_| JamError::TesselationWorkerPanicked)?;
+        results.extend(run);
+    }
+    Ok(results)
+}
+
+// one buffer's worth, in job order
+pub fn merge_vertices(batches: Vec<Vec<Vertex>>) -> Vec<Vertex> {
+    let total = batches.iter().map(|b| b.len()).sum();
+    let mut merged = Vec::with_capacity(total);
+    for batch in batches {
+        merged.extend(batch);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Vec3;
+    use render::quads::GeometryTesselator;
+    use render::texture_region::TextureRegion;
+
+    fn tiles(row: &u32) -> Vec<Vertex> {
+        let tr = TextureRegion { u_min: 0, u_max: 16, v_min: 0, v_max: 16, layer: 0, texture_width: 64, texture_height: 64 };
+        let t = GeometryTesselator::new(Vec3::new(1.0 / 16.0, 1.0 / 16.0, 1.0 / 16.0));
+        let mut vertices = Vec::new();
+        for x in 0..(*row + 1) {
+            t.draw_floor_tile(&mut vertices, &tr, x as f64, 0.0, *row as f64, 0.0);
+        }
+        vertices
+    }
+
+    #[test]
+    fn matches_serial_output() {
+        let jobs : Vec<u32> = (0..37).collect();
+        let serial : Vec<Vec<Vertex>> = jobs.iter().map(tiles).collect();
+        for workers in 1..6 {
+            let parallel = tesselate_parallel(jobs.clone(), workers, tiles).unwrap();
+            assert_eq!(parallel.len(), serial.len());
+            for (p, s) in parallel.iter().zip(serial.iter()) {
+                assert_eq!(p.iter().map(|v| v.position).collect::<Vec<_>>(), s.iter().map(|v| v.position).collect::<Vec<_>>());
+            }
+        }
+        assert_eq!(merge_vertices(serial).len(), 6 * (1..38).sum::<usize>());
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::f64::consts::PI;
+use std::cmp::Ordering;
+
+use cgmath::{Rad, InnerSpace};
+use serde_json;
+use aphid::{HashMap, Seconds};
+
+use {JamResult, JamError, Vec2, Vec3, load_file_contents};
+use color::ColorFloatRaw;
+use render::Vertex;
+use render::command::Blend;
+use render::any_changed;
+use render::quads::GeometryTesselator;
+use render::texture_region::TextureRegion;
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct Burst {
+    pub at: f64, // seconds in to the emitter's cycle
+    pub count: u32,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct ColorKey {
+    pub t: f64, // 0 at birth, 1 at death
+    pub color: ColorFloatRaw,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct SizeKey {
+    pub t: f64,
+    pub size: f64, // multiplies the tesselator scale
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParticleBlend {
+    Alpha,
+    Additive,
+}
+
+impl ParticleBlend {
+    pub fn blend(&self) -> Blend {
+        match *self {
+            ParticleBlend::Alpha => Blend::Alpha,
+            ParticleBlend::Additive => Blend::Add,
+        }
+    }
+}
+
+// (min, max) pairs are picked uniformly per particle
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct EmitterDefinition {
+    pub sprite: String, // sprite name of the particle's region
+    #[serde(default)]
+    pub rate: f64, // particles per second
+    #[serde(default)]
+    pub bursts: Vec<Burst>,
+    #[serde(default)]
+    pub duration: Option<f64>, // None emits until stopped
+    #[serde(default)]
+    pub looping: bool, // repeat the duration, bursts included
+    pub lifetime: (f64, f64),
+    #[serde(default)]
+    pub speed: (f64, f64),
+    #[serde(default = "default_direction")]
+    pub direction: [f64; 3],
+    #[serde(default)]
+    pub spread: f64, // half angle of the cone around direction, in radians
+    #[serde(default)]
+    pub gravity: [f64; 3],
+    #[serde(default)]
+    pub drag: f64, // fraction of velocity lost per second, applied continuously
+    #[serde(default)]
+    pub colors: Vec<ColorKey>, // white when empty
+    #[serde(default)]
+    pub sizes: Vec<SizeKey>, // 1 when empty
+    #[serde(default = "default_blend")]
+    pub blend: ParticleBlend,
+    #[serde(default = "default_max_particles")]
+    pub max_particles: usize,
+}
+
+fn default_direction() -> [f64; 3] { [0.0, 1.0, 0.0] }
+fn default_blend() -> ParticleBlend { ParticleBlend::Alpha }
+fn default_max_particles() -> usize { 1000 }
+
+// piecewise linear over keys sorted by t, clamped at the ends
+fn sample_keys<K, V, T, L>(keys: &[K], t: f64, default: V, key_t: T, lerp: L) -> V where K : Copy, V : Copy, T : Fn(&K) -> (f64, V), L : Fn(V, V, f64) -> V {
+    let first = match keys.first() {
+        Some(k) => key_t(k),
+        None => return default,
+    };
+    if t <= first.0 {
+        return first.1;
+    }
+    for pair in keys.windows(2) {
+        let (t0, v0) = key_t(&pair[0]);
+        let (t1, v1) = key_t(&pair[1]);
+        if t <= t1 {
+            let alpha = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+            return lerp(v0, v1, alpha);
+        }
+    }
+    key_t(keys.last().unwrap()).1
+}
+
+pub fn sample_color(keys: &[ColorKey], t: f64) -> ColorFloatRaw {
+    sample_keys(keys, t, [1.0, 1.0, 1.0, 1.0], |k| (k.t, k.color), |a: ColorFloatRaw, b: ColorFloatRaw, alpha: f64| {
+        let alpha = alpha as f32;
+        [a[0] + (b[0] - a[0]) * alpha, a[1] + (b[1] - a[1]) * alpha, a[2] + (b[2] - a[2]) * alpha, a[3] + (b[3] - a[3]) * alpha]
+    })
+}
+
+pub fn sample_size(keys: &[SizeKey], t: f64) -> f64 {
+    sample_keys(keys, t, 1.0, |k| (k.t, k.size), |a: f64, b: f64, alpha: f64| a + (b - a) * alpha)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Particle {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub age: Seconds,
+    pub lifetime: Seconds,
+}
+
+impl Particle {
+    // 0 at birth, 1 at death
+    pub fn life(&self) -> f64 {
+        if self.lifetime > 0.0 { (self.age / self.lifetime).min(1.0) } else { 1.0 }
+    }
+}
+
+// xorshift, deterministic per seed so effects can be replayed
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ParticleRng {
+    pub state: u64,
+}
+
+impl ParticleRng {
+    pub fn new(seed: u64) -> ParticleRng {
+        ParticleRng { state: if seed == 0 { 0x9E3779B97F4A7C15 } else { seed } }
+    }
+
+    // 0 inclusive to 1 exclusive
+    pub fn next_f64(&mut self) -> f64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        (x.wrapping_mul(0x2545F4914F6CDD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn range(&mut self, range: (f64, f64)) -> f64 {
+        range.0 + (range.1 - range.0) * self.next_f64()
+    }
+}
+
+pub struct ParticleEmitter {
+    pub definition: String, // name in the definitions file
+    pub def: EmitterDefinition,
+    pub position: Vec3, // where new particles spawn, live ones don't follow
+    pub emitting: bool,
+    pub time: Seconds, // in to the current cycle
+    pub spawn_debt: f64, // fractional particles owed by the rate
+    pub particles: Vec<Particle>,
+    pub rng: ParticleRng,
+}
+
+impl ParticleEmitter {
+    pub fn new(definition: &str, def: EmitterDefinition, position: Vec3, seed: u64) -> ParticleEmitter {
+        ParticleEmitter {
+            definition: definition.to_string(),
+            def: def,
+            position: position,
+            emitting: true,
+            time: 0.0,
+            spawn_debt: 0.0,
+            particles: Vec::new(),
+            rng: ParticleRng::new(seed),
+        }
+    }
+
+    pub fn blend(&self) -> Blend {
+        self.def.blend.blend()
+    }
+
+    // live particles carry on
+    pub fn stop(&mut self) {
+        self.emitting = false;
+    }
+
+    pub fn restart(&mut self) {
+        self.emitting = true;
+        self.time = 0.0;
+        self.spawn_debt = 0.0;
+    }
+
+    // safe to drop the emitter
+    pub fn is_finished(&self) -> bool {
+        !self.emitting && self.particles.is_empty()
+    }
+
+    pub fn burst(&mut self, count: u32) {
+        for _ in 0..count {
+            self.spawn();
+        }
+    }
+
+    fn spawn(&mut self) {
+        if self.particles.len() >= self.def.max_particles {
+            return;
+        }
+
+        let direction = Vec3::new(self.def.direction[0], self.def.direction[1], self.def.direction[2]);
+        let direction = if direction.magnitude2() > 0.0 { direction.normalize() } else { Vec3::new(0.0, 1.0, 0.0) };
+
+        // uniform over the cone's cap
+        let cos_theta = 1.0 - self.rng.next_f64() * (1.0 - self.def.spread.cos());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = self.rng.next_f64() * 2.0 * PI;
+        let helper = if direction.x.abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 1.0, 0.0) };
+        let u = direction.cross(helper).normalize();
+        let w = direction.cross(u);
+        let heading = direction * cos_theta + (u * phi.cos() + w * phi.sin()) * sin_theta;
+
+        let speed = self.rng.range(self.def.speed);
+        let lifetime = self.rng.range(self.def.lifetime);
+
+        self.particles.push(Particle {
+            position: self.position,
+            velocity: heading * speed,
+            age: 0.0,
+            lifetime: lifetime,
+        });
+    }
+
+    pub fn update(&mut self, dt: Seconds) {
+        let gravity = Vec3::new(self.def.gravity[0], self.def.gravity[1], self.def.gravity[2]);
+        let damping = (-self.def.drag * dt).exp();
+
+        for p in self.particles.iter_mut() {
+            p.age += dt;
+            p.velocity = (p.velocity + gravity * dt) * damping;
+            p.position += p.velocity * dt;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        // step a cycle at a time so looping bursts aren't skipped
+        let mut remaining = dt;
+        while self.emitting && remaining > 0.0 {
+            let cycle_end = match self.def.duration {
+                Some(d) if d > 0.0 => d,
+                Some(_) => { self.emitting = false; break; },
+                None => ::std::f64::MAX,
+            };
+            let step = remaining.min(cycle_end - self.time);
+            let start = self.time;
+
+            let burst_count : u32 = self.def.bursts.iter().filter(|b| b.at >= start && b.at < start + step).map(|b| b.count).sum();
+            self.burst(burst_count);
+
+            self.spawn_debt += self.def.rate * step;
+            while self.spawn_debt >= 1.0 {
+                self.spawn_debt -= 1.0;
+                self.spawn();
+            }
+
+            self.time += step;
+            remaining -= step;
+
+            if self.time >= cycle_end {
+                if self.def.looping {
+                    self.time = 0.0;
+                } else {
+                    self.emitting = false;
+                }
+            }
+        }
+    }
+
+    // centred billboards sized by the tesselator scale times size over life, coloured by colour over life
+    // draw with self.blend(), alpha blended particles are in spawn order so sort emitters back to front
+    pub fn draw(&self, vertices: &mut Vec<Vertex>, tesselator: &GeometryTesselator, region: &TextureRegion, pitch: Rad<f64>) {
+        let mut t = tesselator.clone();
+        for p in &self.particles {
+            let life = p.life();
+            t.scale = tesselator.scale * sample_size(&self.def.sizes, life);
+            t.color = sample_color(&self.def.colors, life);
+            t.draw_billboard(vertices, region, p.position, Vec2::new(0.5, 0.5), pitch, 0.0);
+        }
+    }
+}
+
+// named emitter definitions from a json object, reloaded when the file changes
+pub struct ParticleDefinitions {
+    pub path: PathBuf,
+    pub emitters: HashMap<String, EmitterDefinition>,
+}
+
+impl ParticleDefinitions {
+    pub fn load(path: &Path) -> JamResult<ParticleDefinitions> {
+        Ok(ParticleDefinitions {
+            path: path.to_path_buf(),
+            emitters: load_particle_definitions(path)?,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> JamResult<&EmitterDefinition> {
+        self.emitters.get(name).ok_or_else(|| JamError::UnknownParticleEmitter(name.to_string()))
+    }
+
+    pub fn emitter(&self, name: &str, position: Vec3, seed: u64) -> JamResult<ParticleEmitter> {
+        Ok(ParticleEmitter::new(name, self.get(name)?.clone(), position, seed))
+    }
+
+    // true when reloaded, a failed reload keeps the previous definitions
+    pub fn reload_if_changed(&mut self, changed: &[PathBuf]) -> JamResult<bool> {
+        let affected = any_changed(changed, &[self.path.clone()]);
+        if affected {
+            self.emitters = load_particle_definitions(&self.path)?;
+        }
+        Ok(affected)
+    }
+
+    // swaps in the emitter's current definition, live particles are kept
+    pub fn refresh(&self, emitter: &mut ParticleEmitter) {
+        if let Some(def) = self.emitters.get(&emitter.definition) {
+            emitter.def = def.clone();
+        }
+    }
+}
+
+pub fn load_particle_definitions(path: &Path) -> JamResult<HashMap<String, EmitterDefinition>> {
+    let bytes = load_file_contents(path)?;
+    parse_particle_definitions(&bytes)
+}
+
+pub fn parse_particle_definitions(bytes: &[u8]) -> JamResult<HashMap<String, EmitterDefinition>> {
+    let mut parsed : BTreeMap<String, EmitterDefinition> = serde_json::from_slice(bytes)?;
+    // sampling assumes keys in t order, hand written json needn't be
+    for def in parsed.values_mut() {
+        def.colors.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(Ordering::Equal));
+        def.sizes.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(Ordering::Equal));
+    }
+    Ok(parsed.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFINITIONS : &'static str = r#"{
+        "sparks": {
+            "sprite": "fx/spark",
+            "rate": 10,
+            "bursts": [{ "at": 0, "count": 5 }],
+            "duration": 1,
+            "looping": true,
+            "lifetime": [0.25, 0.25],
+            "speed": [2, 2],
+            "spread": 0.5,
+            "gravity": [0, -10, 0],
+            "colors": [{ "t": 0, "color": [1, 1, 1, 1] }, { "t": 1, "color": [1, 0, 0, 0] }],
+            "blend": "additive"
+        }
+    }"#;
+
+    #[test]
+    fn emits_bursts_and_rate_then_expires() {
+        let definitions = parse_particle_definitions(DEFINITIONS.as_bytes()).unwrap();
+        let def = definitions.get("sparks").unwrap().clone();
+        assert_eq!(def.blend.blend(), Blend::Add);
+        assert_eq!(def.max_particles, 1000);
+
+        let mut emitter = ParticleEmitter::new("sparks", def, Vec3::new(0.0, 0.0, 0.0), 7);
+        emitter.update(0.2);
+        // the burst plus 2 from the rate
+        assert_eq!(emitter.particles.len(), 7);
+
+        emitter.stop();
+        emitter.update(0.3);
+        assert!(emitter.is_finished());
+    }
+
+    #[test]
+    fn looping_repeats_bursts() {
+        let def = parse_particle_definitions(DEFINITIONS.as_bytes()).unwrap().get("sparks").unwrap().clone();
+        let def = EmitterDefinition { rate: 0.0, lifetime: (10.0, 10.0), .. def };
+        let mut emitter = ParticleEmitter::new("sparks", def, Vec3::new(0.0, 0.0, 0.0), 7);
+        emitter.update(2.5);
+        assert_eq!(emitter.particles.len(), 15);
+    }
+
+    #[test]
+    fn colour_over_life_interpolates() {
+        let keys = [ColorKey { t: 0.0, color: [1.0, 1.0, 1.0, 1.0] }, ColorKey { t: 1.0, color: [1.0, 0.0, 0.0, 0.0] }];
+        assert_eq!(sample_color(&keys, 0.5), [1.0, 0.5, 0.5, 0.5]);
+        assert_eq!(sample_color(&keys, 2.0), [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(sample_size(&[], 0.5), 1.0);
+    }
+
+    #[test]
+    fn keys_are_sorted_by_t() {
+        let json = r#"{ "smoke": { "sprite": "fx/smoke", "lifetime": [1, 1], "sizes": [{ "t": 1, "size": 3 }, { "t": 0, "size": 1 }] } }"#;
+        let defs = parse_particle_definitions(json.as_bytes()).unwrap();
+        let sizes = &defs["smoke"].sizes;
+        assert_eq!(sizes[0].t, 0.0);
+        assert_eq!(sample_size(sizes, 0.5), 2.0);
+    }
+}
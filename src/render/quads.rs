@@ -1,8 +1,8 @@
 extern crate cgmath;
 
-use {Vec3};
+use {Vec3, Mat4};
 use color::*;
-use cgmath::{Rad, Matrix3, Point3, Transform};
+use cgmath::{Rad, Matrix3, Point3, Vector3, Transform, SquareMatrix, Matrix, InnerSpace};
 use render::Vertex;
 use render::texture_region::TextureRegion;
 
@@ -14,9 +14,11 @@ pub fn add_quad<T>(vertices: &mut Vec<T>, ts:[T; 4]) where T : Copy {
     vertices.push(ts[2]);
 }
 
+#[derive(Clone, Debug)]
 pub struct GeometryTesselator {
     pub scale: Vec3, // scale ... translating pixel coord to real world coords
     pub color: ColorFloatRaw,
+    pub transforms: Vec<(Mat4, Mat4)>, // accumulated (position, normal) transforms, empty is identity
 }
 
 const X_POS : [f32; 3] = [1.0, 0.0, 0.0];
@@ -30,6 +32,72 @@ impl GeometryTesselator {
         GeometryTesselator {
             scale : scale,
             color : WHITE.float_raw(),
+            transforms: Vec::new(),
+        }
+    }
+
+    // the current local to world transform
+    pub fn transform(&self) -> Mat4 {
+        self.transforms.last().map(|t| t.0).unwrap_or(Mat4::identity())
+    }
+
+    // composes with the current transform, so later pushes are relative to earlier ones
+    pub fn push_transform(&mut self, m: Mat4) {
+        let transform = self.transform() * m;
+        // inverse transpose keeps normals perpendicular under non uniform scale
+        let normal = transform.invert().map(|i| i.transpose()).unwrap_or(transform);
+        self.transforms.push((transform, normal));
+    }
+
+    pub fn push_translate(&mut self, v: Vec3) {
+        self.push_transform(Mat4::from_translation(v));
+    }
+
+    pub fn push_rotate_x(&mut self, theta: Rad<f64>) {
+        self.push_transform(Mat4::from_angle_x(theta));
+    }
+
+    pub fn push_rotate_y(&mut self, theta: Rad<f64>) {
+        self.push_transform(Mat4::from_angle_y(theta));
+    }
+
+    pub fn push_rotate_z(&mut self, theta: Rad<f64>) {
+        self.push_transform(Mat4::from_angle_z(theta));
+    }
+
+    pub fn push_scale(&mut self, s: Vec3) {
+        self.push_transform(Mat4::from_nonuniform_scale(s.x, s.y, s.z));
+    }
+
+    pub fn pop_transform(&mut self) -> Option<Mat4> {
+        self.transforms.pop().map(|t| t.0)
+    }
+
+    pub fn clear_transforms(&mut self) {
+        self.transforms.clear();
+    }
+
+    // every draw_ method goes through these
+    pub fn emit_vertex(&self, vertices: &mut Vec<Vertex>, vertex: Vertex) {
+        vertices.push(self.transformed(vertex));
+    }
+
+    pub fn emit_quad(&self, vertices: &mut Vec<Vertex>, ts: [Vertex; 4]) {
+        add_quad(vertices, [self.transformed(ts[0]), self.transformed(ts[1]), self.transformed(ts[2]), self.transformed(ts[3])]);
+    }
+
+    fn transformed(&self, vertex: Vertex) -> Vertex {
+        let &(ref transform, ref normal_transform) = match self.transforms.last() {
+            Some(t) => t,
+            None => return vertex,
+        };
+        let p = transform.transform_point(Point3::new(vertex.position[0] as f64, vertex.position[1] as f64, vertex.position[2] as f64));
+        let n = normal_transform.transform_vector(Vector3::new(vertex.normal[0] as f64, vertex.normal[1] as f64, vertex.normal[2] as f64));
+        let n = if n.magnitude2() > 0.0 { n.normalize() } else { n };
+        Vertex {
+            position: [p.x as f32, p.y as f32, p.z as f32],
+            normal: [n.x as f32, n.y as f32, n.z as f32],
+            .. vertex
         }
     }
 
@@ -46,7 +114,7 @@ impl GeometryTesselator {
         let nu_left = tr.nu_min();
         let nu_right = tr.nu_max();
 
-        add_quad(vertices, [
+        self.emit_quad(vertices, [
             Vertex { position: [ax as f32,        (y + depth_adjust) as f32, (az + dw + depth_adjust) as f32], tex_coord: [nu_left , tr.nv_min(), layer_f], color: self.color, normal: Y_POS },
             Vertex { position: [(ax + ww) as f32, (y + depth_adjust) as f32, (az + dw + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_min(), layer_f], color: self.color, normal: Y_POS },
             Vertex { position: [(ax + ww) as f32, (y + depth_adjust) as f32, (az + depth_adjust     ) as f32], tex_coord: [nu_right, tr.nv_max(), layer_f], color: self.color, normal: Y_POS },
@@ -68,7 +136,7 @@ impl GeometryTesselator {
         let nu_left = tr.nu_min();
         let nu_right = tr.nu_max();
 
-        add_quad(vertices, [
+        self.emit_quad(vertices, [
             Vertex { position: [ax as f32,        (ay + depth_adjust) as f32,      (z + depth_adjust) as f32], tex_coord: [nu_left , tr.nv_min(), layer_f], color: self.color, normal: Y_POS },
             Vertex { position: [(ax + ww) as f32, (ay + depth_adjust) as f32,      (z + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_min(), layer_f], color: self.color, normal: Y_POS },
             Vertex { position: [(ax + ww) as f32, (ay + depth_adjust + hw) as f32, (z + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_max(), layer_f], color: self.color, normal: Y_POS },
@@ -91,7 +159,7 @@ impl GeometryTesselator {
         let nu_right = tr.nu_max();
 
 
-        add_quad(vertices, [
+        self.emit_quad(vertices, [
             Vertex { position: [(ax - hww) as f32, (y + depth_adjust) as f32, (az + hdw + depth_adjust) as f32], tex_coord: [nu_left , tr.nv_min(), layer_f], color: self.color, normal: Y_POS },
             Vertex { position: [(ax + hww) as f32, (y + depth_adjust) as f32, (az + hdw + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_min(), layer_f], color: self.color, normal: Y_POS },
             Vertex { position: [(ax + hww) as f32, (y + depth_adjust) as f32, (az - hdw + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_max(), layer_f], color: self.color, normal: Y_POS },
@@ -120,7 +188,7 @@ impl GeometryTesselator {
         let yy = y + depth_adjust;
         let zz = az + depth_adjust;
 
-        add_quad(vertices, [
+        self.emit_quad(vertices, [
             Vertex { position: [(p0.x + xx) as f32, (p0.y + yy + depth_adjust) as f32, (p0.z + zz + depth_adjust) as f32], tex_coord: [tr.nu_min(), tr.nv_min(), layer_f], color: self.color, normal: Y_POS },
             Vertex { position: [(p1.x + xx) as f32, (p1.y + yy + depth_adjust) as f32, (p1.z + zz + depth_adjust) as f32], tex_coord: [tr.nu_max(), tr.nv_min(), layer_f], color: self.color, normal: Y_POS },
             Vertex { position: [(p2.x + xx) as f32, (p2.y + yy + depth_adjust) as f32, (p2.z + zz + depth_adjust) as f32], tex_coord: [tr.nu_max(), tr.nv_max(), layer_f], color: self.color, normal: Y_POS },
@@ -141,7 +209,7 @@ impl GeometryTesselator {
         let nu_left = tr.nu_min();
         let nu_right = tr.nu_max();
 
-        add_quad(vertices, [
+        self.emit_quad(vertices, [
             Vertex { position: [(ax - hww) as f32, (ay + depth_adjust) as f32,       (z + depth_adjust) as f32], tex_coord: [nu_left , tr.nv_min(), layer_f], color: self.color, normal: Z_POS },
             Vertex { position: [(ax + hww) as f32, (ay + depth_adjust) as f32,       (z + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_min(), layer_f], color: self.color, normal: Z_POS },
             Vertex { position: [(ax + hww) as f32, (ay + hhw + depth_adjust) as f32, (z + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_max(), layer_f], color: self.color, normal: Z_POS },
@@ -161,7 +229,7 @@ impl GeometryTesselator {
         let nu_left = tr.nu_min();
         let nu_right = tr.nu_max();
 
-        add_quad(vertices, [
+        self.emit_quad(vertices, [
             Vertex { position: [(ax - hww) as f32, (ay + depth_adjust) as f32,       (z + depth_adjust) as f32], tex_coord: [nu_left , tr.nv_min(), layer_f], color: self.color, normal: Z_POS },
             Vertex { position: [(ax + hww) as f32, (ay + depth_adjust) as f32,       (z + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_min(), layer_f], color: self.color, normal: Z_POS },
             Vertex { position: [(ax + hww) as f32, (ay + height + depth_adjust) as f32, (z + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_max(), layer_f], color: self.color, normal: Z_POS },
@@ -183,7 +251,7 @@ impl GeometryTesselator {
         let nu_left = tr.nu_min();
         let nu_right = tr.nu_max();
 
-        add_quad(vertices, [
+        self.emit_quad(vertices, [
             Vertex { position: [(ax - hww) as f32, (ay - hhw + depth_adjust) as f32, (z + depth_adjust) as f32], tex_coord: [nu_left , tr.nv_min(), layer_f], color: self.color, normal: Z_POS },
             Vertex { position: [(ax + hww) as f32, (ay - hhw + depth_adjust) as f32, (z + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_min(), layer_f], color: self.color, normal: Z_POS },
             Vertex { position: [(ax + hww) as f32, (ay + hhw + depth_adjust) as f32, (z + depth_adjust) as f32], tex_coord: [nu_right, tr.nv_max(), layer_f], color: self.color, normal: Z_POS },
@@ -200,7 +268,7 @@ impl GeometryTesselator {
         let nu_left = tr.nu_min();
         let nu_right = tr.nu_max();
 
-        add_quad(vertices, [
+        self.emit_quad(vertices, [
             Vertex { position: [x as f32,        (y) as f32,      z as f32], tex_coord: [nu_left , tr.nv_min(), layer_f], color: self.color, normal: Z_POS },
             Vertex { position: [(x + ww) as f32, (y) as f32,      z as f32], tex_coord: [nu_right, tr.nv_min(), layer_f], color: self.color, normal: Z_POS },
             Vertex { position: [(x + ww) as f32, (y + hw) as f32, z as f32], tex_coord: [nu_right, tr.nv_max(), layer_f], color: self.color, normal: Z_POS },
@@ -216,7 +284,7 @@ impl GeometryTesselator {
         let nu_left = tr.nu_min();
         let nu_right = tr.nu_max();
 
-        add_quad(vertices, [
+        self.emit_quad(vertices, [
             Vertex { position: [(x - hww) as f32, (y - hhw) as f32, z as f32], tex_coord: [nu_left , tr.nv_min(), layer_f], color: self.color, normal: Z_POS },
             Vertex { position: [(x + hww) as f32, (y - hhw) as f32, z as f32], tex_coord: [nu_right, tr.nv_min(), layer_f], color: self.color, normal: Z_POS },
             Vertex { position: [(x + hww) as f32, (y + hhw) as f32, z as f32], tex_coord: [nu_right, tr.nv_max(), layer_f], color: self.color, normal: Z_POS },
@@ -224,3 +292,32 @@ impl GeometryTesselator {
         ]);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms_apply_to_positions_and_normals() {
+        let tr = TextureRegion { u_min: 0, u_max: 16, v_min: 0, v_max: 16, layer: 0, texture_width: 64, texture_height: 64 };
+        let mut t = GeometryTesselator::new(Vec3::new(1.0 / 16.0, 1.0 / 16.0, 1.0 / 16.0));
+
+        t.push_translate(Vec3::new(10.0, 0.0, 0.0));
+        t.push_rotate_z(Rad(::std::f64::consts::PI / 2.0));
+        let mut vertices = Vec::new();
+        t.draw_floor_tile(&mut vertices, &tr, 0.0, 0.0, 0.0, 0.0);
+
+        // the floor's up turns to face -x, about the translated origin
+        let n = vertices[0].normal;
+        assert!((n[0] + 1.0).abs() < 0.0001 && n[1].abs() < 0.0001);
+        let corner = vertices[1].position;
+        assert!((corner[0] - 10.0).abs() < 0.0001 && (corner[1] - 1.0).abs() < 0.0001);
+
+        assert!(t.pop_transform().is_some());
+        t.pop_transform();
+        assert!(t.pop_transform().is_none());
+        let mut untransformed = Vec::new();
+        t.draw_floor_tile(&mut untransformed, &tr, 0.0, 0.0, 0.0, 0.0);
+        assert_eq!(untransformed[1].position, [1.0, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,284 @@
+use std::f64::consts::PI;
+
+use cgmath::InnerSpace;
+
+use {Vec2};
+use color::ColorFloatRaw;
+use render::Vertex;
+use render::quads::GeometryTesselator;
+use render::texture_region::TextureRegion;
+
+// everything here lies in the x/y plane at depth z like draw_ui, coloured by the tesselator colour unless
+// given per corner colours, and samples the middle of a white region so it shares the sprite pipeline, for
+// renderer.draw that's renderer.texture_region(WHITE_REGION), reserved in every loaded texture
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LineJoin {
+    Miter, // falls back to bevel past MITER_LIMIT
+    Bevel,
+    Round,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Square, // extended by half the thickness
+    Round,
+}
+
+// longest a miter may reach from the joint, in half thicknesses
+pub const MITER_LIMIT : f64 = 4.0;
+
+const Z_POS : [f32; 3] = [0.0, 0.0, 1.0];
+
+// left of the direction of travel
+fn left_normal(d: Vec2) -> Vec2 {
+    Vec2::new(-d.y, d.x)
+}
+
+fn cross(a: Vec2, b: Vec2) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+// enough segments that a round join or cap looks smooth at the given sweep
+fn round_segments(sweep: f64) -> u32 {
+    ((sweep.abs() / (PI / 8.0)).ceil() as u32).max(1)
+}
+
+fn arc_points(centre: Vec2, radius: f64, start: f64, end: f64, segments: u32) -> Vec<Vec2> {
+    let segments = segments.max(1);
+    (0..(segments + 1)).map(|i| {
+        let theta = start + (end - start) * (i as f64) / (segments as f64);
+        centre + Vec2::new(theta.cos(), theta.sin()) * radius
+    }).collect()
+}
+
+impl GeometryTesselator {
+    fn shape_vertex(&self, white: &TextureRegion, p: Vec2, z: f64, color: ColorFloatRaw) -> Vertex {
+        Vertex { position: [p.x as f32, p.y as f32, z as f32], tex_coord: [white.nu_mid(), white.nv_mid(), white.layer as f32], color: color, normal: Z_POS }
+    }
+
+    // wound counter clockwise whatever order the corners come in
+    fn shape_triangle(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, a: Vec2, b: Vec2, c: Vec2, z: f64) {
+        let (b, c) = if cross(b - a, c - a) < 0.0 { (c, b) } else { (b, c) };
+        self.emit_vertex(vertices, self.shape_vertex(white, a, z, self.color));
+        self.emit_vertex(vertices, self.shape_vertex(white, b, z, self.color));
+        self.emit_vertex(vertices, self.shape_vertex(white, c, z, self.color));
+    }
+
+    fn shape_fan(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, centre: Vec2, ring: &[Vec2], z: f64) {
+        for pair in ring.windows(2) {
+            self.shape_triangle(vertices, white, centre, pair[0], pair[1], z);
+        }
+    }
+
+    // corners are bottom left, bottom right, top right, top left
+    pub fn draw_gradient_rect(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, x: f64, y: f64, z: f64, width: f64, height: f64, colors: [ColorFloatRaw; 4]) {
+        self.emit_quad(vertices, [
+            self.shape_vertex(white, Vec2::new(x, y), z, colors[0]),
+            self.shape_vertex(white, Vec2::new(x + width, y), z, colors[1]),
+            self.shape_vertex(white, Vec2::new(x + width, y + height), z, colors[2]),
+            self.shape_vertex(white, Vec2::new(x, y + height), z, colors[3])
+        ]);
+    }
+
+    pub fn draw_vertical_gradient_rect(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, x: f64, y: f64, z: f64, width: f64, height: f64, bottom: ColorFloatRaw, top: ColorFloatRaw) {
+        self.draw_gradient_rect(vertices, white, x, y, z, width, height, [bottom, bottom, top, top]);
+    }
+
+    pub fn draw_horizontal_gradient_rect(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, x: f64, y: f64, z: f64, width: f64, height: f64, left: ColorFloatRaw, right: ColorFloatRaw) {
+        self.draw_gradient_rect(vertices, white, x, y, z, width, height, [left, right, right, left]);
+    }
+
+    // points in order around the edge, either winding
+    pub fn draw_convex_polygon(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, points: &[Vec2], z: f64) {
+        if points.len() < 3 {
+            return;
+        }
+        self.shape_fan(vertices, white, points[0], &points[1..], z);
+    }
+
+    pub fn draw_circle(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, centre: Vec2, radius: f64, z: f64, segments: u32) {
+        let ring = arc_points(centre, radius, 0.0, 2.0 * PI, segments.max(3));
+        self.shape_fan(vertices, white, centre, &ring, z);
+    }
+
+    // the thickness straddles the radius
+    pub fn draw_circle_outline(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, centre: Vec2, radius: f64, thickness: f64, z: f64, segments: u32) {
+        let segments = segments.max(3);
+        let mut ring = arc_points(centre, radius, 0.0, 2.0 * PI, segments);
+        ring.pop(); // the last point repeats the first
+        self.draw_polyline(vertices, white, &ring, thickness, z, LineJoin::Miter, LineCap::Butt, true);
+    }
+
+    // a pie slice, angles in radians counter clockwise from +x
+    pub fn draw_sector(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, centre: Vec2, radius: f64, start: f64, end: f64, z: f64, segments: u32) {
+        let ring = arc_points(centre, radius, start, end, segments);
+        self.shape_fan(vertices, white, centre, &ring, z);
+    }
+
+    // a stroked arc
+    pub fn draw_arc(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, centre: Vec2, radius: f64, start: f64, end: f64, thickness: f64, z: f64, segments: u32, cap: LineCap) {
+        let points = arc_points(centre, radius, start, end, segments);
+        self.draw_polyline(vertices, white, &points, thickness, z, LineJoin::Miter, cap, false);
+    }
+
+    pub fn draw_convex_polygon_outline(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, points: &[Vec2], thickness: f64, z: f64, join: LineJoin) {
+        self.draw_polyline(vertices, white, points, thickness, z, join, LineCap::Butt, true);
+    }
+
+    // closed joins the last point back to the first and ignores the cap
+    pub fn draw_polyline(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, points: &[Vec2], thickness: f64, z: f64, join: LineJoin, cap: LineCap, closed: bool) {
+        let h = thickness / 2.0;
+
+        // repeated points have no direction
+        let mut ps : Vec<Vec2> = Vec::with_capacity(points.len());
+        for &p in points {
+            if ps.last().map(|&l: &Vec2| (p - l).magnitude2() > 0.0).unwrap_or(true) {
+                ps.push(p);
+            }
+        }
+        if closed && ps.len() > 2 && (ps[0] - ps[ps.len() - 1]).magnitude2() == 0.0 {
+            ps.pop();
+        }
+        if ps.len() < 2 {
+            return;
+        }
+
+        let segment_count = if closed { ps.len() } else { ps.len() - 1 };
+        let segment = |i: usize| -> (Vec2, Vec2) { (ps[i], ps[(i + 1) % ps.len()]) };
+
+        for i in 0..segment_count {
+            let (mut a, mut b) = segment(i);
+            let d = (b - a).normalize();
+            if !closed && cap == LineCap::Square {
+                if i == 0 {
+                    a = a - d * h;
+                }
+                if i == segment_count - 1 {
+                    b = b + d * h;
+                }
+            }
+            let n = left_normal(d) * h;
+            self.emit_quad(vertices, [
+                self.shape_vertex(white, a - n, z, self.color),
+                self.shape_vertex(white, b - n, z, self.color),
+                self.shape_vertex(white, b + n, z, self.color),
+                self.shape_vertex(white, a + n, z, self.color)
+            ]);
+        }
+
+        // joins between each segment and the next
+        let join_count = if closed { ps.len() } else { ps.len() - 2 };
+        for i in 0..join_count {
+            let (a, p) = segment(i);
+            let (_, c) = segment((i + 1) % segment_count);
+            self.draw_line_join(vertices, white, (p - a).normalize(), p, (c - p).normalize(), h, z, join);
+        }
+
+        if !closed && cap == LineCap::Round {
+            let d_start = (ps[1] - ps[0]).normalize();
+            let d_end = (ps[ps.len() - 1] - ps[ps.len() - 2]).normalize();
+            self.draw_round_cap(vertices, white, ps[0], d_start * -1.0, h, z);
+            self.draw_round_cap(vertices, white, ps[ps.len() - 1], d_end, h, z);
+        }
+    }
+
+    // fills the wedge on the outside of the turn at p, d0 arriving and d1 leaving
+    fn draw_line_join(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, d0: Vec2, p: Vec2, d1: Vec2, h: f64, z: f64, join: LineJoin) {
+        let turn = cross(d0, d1);
+        if turn.abs() < 0.000001 && d0.dot(d1) > 0.0 {
+            return; // straight on
+        }
+        // turning left opens a gap on the right
+        let side = if turn > 0.0 { -1.0 } else { 1.0 };
+        let outer0 = p + left_normal(d0) * (h * side);
+        let outer1 = p + left_normal(d1) * (h * side);
+
+        match join {
+            LineJoin::Bevel => self.shape_triangle(vertices, white, p, outer0, outer1, z),
+            LineJoin::Miter => {
+                let bisector = (outer0 - p) + (outer1 - p);
+                let cos_half = if bisector.magnitude2() > 0.0 { (outer0 - p).normalize().dot(bisector.normalize()) } else { 0.0 };
+                if cos_half <= 1.0 / MITER_LIMIT {
+                    self.shape_triangle(vertices, white, p, outer0, outer1, z);
+                } else {
+                    let tip = p + bisector.normalize() * (h / cos_half);
+                    self.shape_triangle(vertices, white, p, outer0, tip, z);
+                    self.shape_triangle(vertices, white, p, tip, outer1, z);
+                }
+            },
+            LineJoin::Round => {
+                let a0 = (outer0.y - p.y).atan2(outer0.x - p.x);
+                let mut sweep = (outer1.y - p.y).atan2(outer1.x - p.x) - a0;
+                while sweep > PI { sweep -= 2.0 * PI; }
+                while sweep < -PI { sweep += 2.0 * PI; }
+                let ring = arc_points(p, h, a0, a0 + sweep, round_segments(sweep));
+                self.shape_fan(vertices, white, p, &ring, z);
+            },
+        }
+    }
+
+    // a half disc at p bulging towards outward
+    fn draw_round_cap(&self, vertices: &mut Vec<Vertex>, white: &TextureRegion, p: Vec2, outward: Vec2, h: f64, z: f64) {
+        let n = left_normal(outward);
+        let start = n.y.atan2(n.x);
+        // from +n through outward to -n is clockwise
+        let ring = arc_points(p, h, start, start - PI, round_segments(PI));
+        self.shape_fan(vertices, white, p, &ring, z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Vec3;
+
+    fn white() -> TextureRegion {
+        TextureRegion { u_min: 1, u_max: 3, v_min: 1, v_max: 3, layer: 0, texture_width: 64, texture_height: 64 }
+    }
+
+    fn counter_clockwise(vertices: &[Vertex]) -> bool {
+        vertices.chunks(3).all(|t| {
+            let p = |i: usize| Vec2::new(t[i].position[0] as f64, t[i].position[1] as f64);
+            cross(p(1) - p(0), p(2) - p(0)) >= 0.0
+        })
+    }
+
+    #[test]
+    fn circles_and_polygons_fan_out() {
+        let t = GeometryTesselator::new(Vec3::new(1.0, 1.0, 1.0));
+        let mut vertices = Vec::new();
+        t.draw_circle(&mut vertices, &white(), Vec2::new(0.0, 0.0), 2.0, 0.0, 16);
+        assert_eq!(vertices.len(), 16 * 3);
+
+        let square = [Vec2::new(0.0, 0.0), Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0), Vec2::new(1.0, 0.0)];
+        t.draw_convex_polygon(&mut vertices, &white(), &square, 0.0);
+        assert_eq!(vertices.len(), 18 * 3);
+        assert!(counter_clockwise(&vertices));
+        assert!(vertices.iter().all(|v| v.tex_coord == [white().nu_mid(), white().nv_mid(), 0.0]));
+    }
+
+    #[test]
+    fn miter_joins_meet_at_the_corner() {
+        let t = GeometryTesselator::new(Vec3::new(1.0, 1.0, 1.0));
+        let mut vertices = Vec::new();
+        let points = [Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0), Vec2::new(4.0, 4.0)];
+        t.draw_polyline(&mut vertices, &white(), &points, 2.0, 0.0, LineJoin::Miter, LineCap::Butt, false);
+        // two segment quads and the two miter triangles
+        assert_eq!(vertices.len(), 2 * 6 + 2 * 3);
+        assert!(vertices.iter().any(|v| v.position == [5.0, -1.0, 0.0]));
+        assert!(counter_clockwise(&vertices));
+    }
+
+    #[test]
+    fn gradient_corners_keep_their_colours() {
+        let t = GeometryTesselator::new(Vec3::new(1.0, 1.0, 1.0));
+        let mut vertices = Vec::new();
+        let red = [1.0, 0.0, 0.0, 1.0];
+        let blue = [0.0, 0.0, 1.0, 1.0];
+        t.draw_vertical_gradient_rect(&mut vertices, &white(), 0.0, 0.0, 0.0, 10.0, 10.0, red, blue);
+        assert_eq!(vertices[0].color, red);
+        assert_eq!(vertices[2].color, blue);
+    }
+}
@@ -0,0 +1,305 @@
+use render::Vertex;
+use render::quads::GeometryTesselator;
+use render::texture_region::TextureRegion;
+
+const Y_POS : [f32; 3] = [0.0, 1.0, 0.0];
+const Z_POS : [f32; 3] = [0.0, 0.0, 1.0];
+
+// in texels of the region, bottom/top follow the region's v direction
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SliceInsets {
+    pub left: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub top: u32,
+}
+
+impl SliceInsets {
+    pub fn uniform(inset: u32) -> SliceInsets {
+        SliceInsets {
+            left: inset,
+            right: inset,
+            bottom: inset,
+            top: inset,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SliceMode {
+    Stretch,
+    Tile, // repeats at the source size, the last repeat is cut short
+}
+
+// corners are drawn at their source size, edges and centre fill the rest
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct NineSlice {
+    pub region: TextureRegion,
+    pub insets: SliceInsets,
+    pub edges: SliceMode,
+    pub centre: SliceMode,
+}
+
+impl NineSlice {
+    pub fn new(region: TextureRegion, insets: SliceInsets) -> NineSlice {
+        NineSlice {
+            region: region,
+            insets: insets,
+            edges: SliceMode::Stretch,
+            centre: SliceMode::Stretch,
+        }
+    }
+
+    pub fn with_modes(self, edges: SliceMode, centre: SliceMode) -> NineSlice {
+        NineSlice {
+            edges: edges,
+            centre: centre,
+            .. self
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SliceAxis {
+    Horizontal,
+    Vertical,
+}
+
+// caps at each end of one axis, the other axis is drawn at its source size
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ThreeSlice {
+    pub region: TextureRegion,
+    pub axis: SliceAxis,
+    pub start: u32, // left or bottom cap in texels
+    pub end: u32,
+    pub mode: SliceMode,
+}
+
+impl ThreeSlice {
+    pub fn new(region: TextureRegion, axis: SliceAxis, start: u32, end: u32) -> ThreeSlice {
+        ThreeSlice {
+            region: region,
+            axis: axis,
+            start: start,
+            end: end,
+            mode: SliceMode::Stretch,
+        }
+    }
+
+    pub fn with_mode(self, mode: SliceMode) -> ThreeSlice {
+        ThreeSlice {
+            mode: mode,
+            .. self
+        }
+    }
+
+    pub fn nine_slice(&self) -> NineSlice {
+        let insets = match self.axis {
+            SliceAxis::Horizontal => SliceInsets { left: self.start, right: self.end, bottom: 0, top: 0 },
+            SliceAxis::Vertical => SliceInsets { left: 0, right: 0, bottom: self.start, top: self.end },
+        };
+        NineSlice {
+            region: self.region,
+            insets: insets,
+            edges: self.mode,
+            centre: self.mode,
+        }
+    }
+
+    // (width, height) in texels for a given length along the axis
+    fn size_for(&self, length: f64, scale_across: f64) -> (f64, f64) {
+        match self.axis {
+            SliceAxis::Horizontal => (length, self.region.height() as f64 * scale_across),
+            SliceAxis::Vertical => (self.region.width() as f64 * scale_across, length),
+        }
+    }
+}
+
+// a span of output along one axis and the texel offsets (from the region's min edge) it samples
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SliceSegment {
+    pub from: f64,
+    pub to: f64,
+    pub texel_from: f64,
+    pub texel_to: f64,
+}
+
+// splits length in to start cap, middle and end cap, caps shrink evenly when there isn't room for them
+pub fn slice_segments(source_length: u32, start: u32, end: u32, length: f64, scale: f64, mode: SliceMode) -> Vec<SliceSegment> {
+    let mut segments = Vec::new();
+
+    let start = start.min(source_length);
+    let end = end.min(source_length - start);
+    let source = source_length as f64;
+
+    let caps = (start + end) as f64 * scale;
+    if caps >= length {
+        let shrink = if caps > 0.0 { length / caps } else { 0.0 };
+        let split = start as f64 * scale * shrink;
+        push_segment(&mut segments, 0.0, split, 0.0, start as f64);
+        push_segment(&mut segments, split, length, source - end as f64, source);
+        return segments;
+    }
+
+    let middle_from = start as f64 * scale;
+    let middle_to = length - end as f64 * scale;
+    let middle_texels = (source_length - start - end) as f64;
+
+    push_segment(&mut segments, 0.0, middle_from, 0.0, start as f64);
+
+    let tile_length = middle_texels * scale;
+    if mode == SliceMode::Tile && tile_length > 0.0 {
+        let mut at = middle_from;
+        while at < middle_to {
+            let next = (at + tile_length).min(middle_to);
+            push_segment(&mut segments, at, next, start as f64, start as f64 + (next - at) / scale);
+            at = next;
+        }
+    } else {
+        push_segment(&mut segments, middle_from, middle_to, start as f64, start as f64 + middle_texels);
+    }
+
+    push_segment(&mut segments, middle_to, length, source - end as f64, source);
+
+    segments
+}
+
+fn push_segment(segments: &mut Vec<SliceSegment>, from: f64, to: f64, texel_from: f64, texel_to: f64) {
+    if to > from {
+        segments.push(SliceSegment { from: from, to: to, texel_from: texel_from, texel_to: texel_to });
+    }
+}
+
+// whether a segment samples the stretchable/tiled part of the source rather than a cap
+fn is_middle(segment: &SliceSegment, start: u32, source: u32, end: u32) -> bool {
+    source > start + end && segment.texel_from >= start as f64 && segment.texel_to <= (source - end) as f64
+}
+
+// normalized texture coordinate for a texel offset, flipped regions sample backwards
+fn nu_at(tr: &TextureRegion, texel: f64) -> f32 {
+    let direction = if tr.u_max >= tr.u_min { 1.0 } else { -1.0 };
+    ((tr.u_min as f64 + texel * direction) / tr.texture_width as f64) as f32
+}
+
+fn nv_at(tr: &TextureRegion, texel: f64) -> f32 {
+    let direction = if tr.v_max >= tr.v_min { 1.0 } else { -1.0 };
+    ((tr.v_min as f64 + texel * direction) / tr.texture_height as f64) as f32
+}
+
+impl GeometryTesselator {
+    // place maps a local (x, y) in the slice's plane to a position
+    fn draw_slices<F>(&self, vertices: &mut Vec<Vertex>, slice: &NineSlice, width: f64, height: f64, scale_x: f64, scale_y: f64, normal: [f32; 3], place: F) where F : Fn(f64, f64) -> [f32; 3] {
+        let tr = &slice.region;
+        let insets = slice.insets;
+
+        let xs = slice_segments(tr.width(), insets.left, insets.right, width, scale_x, slice.edges);
+        let ys = slice_segments(tr.height(), insets.bottom, insets.top, height, scale_y, slice.edges);
+        let centre_xs = slice_segments(tr.width(), insets.left, insets.right, width, scale_x, slice.centre);
+        let centre_ys = slice_segments(tr.height(), insets.bottom, insets.top, height, scale_y, slice.centre);
+
+        let middle_x = |s: &SliceSegment| is_middle(s, insets.left, tr.width(), insets.right);
+        let middle_y = |s: &SliceSegment| is_middle(s, insets.bottom, tr.height(), insets.top);
+
+        // corners and edges
+        for y in &ys {
+            for x in &xs {
+                if !(middle_x(x) && middle_y(y)) {
+                    self.slice_quad(vertices, tr, x, y, normal, &place);
+                }
+            }
+        }
+
+        for y in centre_ys.iter().filter(|s| middle_y(s)) {
+            for x in centre_xs.iter().filter(|s| middle_x(s)) {
+                self.slice_quad(vertices, tr, x, y, normal, &place);
+            }
+        }
+    }
+
+    fn slice_quad<F>(&self, vertices: &mut Vec<Vertex>, tr: &TextureRegion, x: &SliceSegment, y: &SliceSegment, normal: [f32; 3], place: &F) where F : Fn(f64, f64) -> [f32; 3] {
+        let layer_f = tr.layer as f32;
+        let (u0, u1) = (nu_at(tr, x.texel_from), nu_at(tr, x.texel_to));
+        let (v0, v1) = (nv_at(tr, y.texel_from), nv_at(tr, y.texel_to));
+
+        self.emit_quad(vertices, [
+            Vertex { position: place(x.from, y.from), tex_coord: [u0, v0, layer_f], color: self.color, normal: normal },
+            Vertex { position: place(x.to,   y.from), tex_coord: [u1, v0, layer_f], color: self.color, normal: normal },
+            Vertex { position: place(x.to,   y.to),   tex_coord: [u1, v1, layer_f], color: self.color, normal: normal },
+            Vertex { position: place(x.from, y.to),   tex_coord: [u0, v1, layer_f], color: self.color, normal: normal },
+        ]);
+    }
+
+    // x/y is the bottom left, width/height in ui units, scale is the size of a texel as in draw_ui
+    pub fn draw_ui_nine_slice(&self, vertices: &mut Vec<Vertex>, slice: &NineSlice, x: f64, y: f64, z: f64, width: f64, height: f64, scale: f64) {
+        self.draw_slices(vertices, slice, width, height, scale, scale, Z_POS, |px, py| {
+            [(x + px) as f32, (y + py) as f32, z as f32]
+        });
+    }
+
+    // anchor is the bottom left, standing up in x/y like draw_wall_tile
+    pub fn draw_wall_nine_slice(&self, vertices: &mut Vec<Vertex>, slice: &NineSlice, ax: f64, ay: f64, z: f64, width: f64, height: f64, depth_adjust: f64) {
+        self.draw_slices(vertices, slice, width, height, self.scale.x, self.scale.y, Z_POS, |px, py| {
+            [(ax + px) as f32, (ay + py + depth_adjust) as f32, (z + depth_adjust) as f32]
+        });
+    }
+
+    // anchor is near x/z like draw_floor_tile, the bottom of the region is at az + depth
+    pub fn draw_floor_nine_slice(&self, vertices: &mut Vec<Vertex>, slice: &NineSlice, ax: f64, y: f64, az: f64, width: f64, depth: f64, depth_adjust: f64) {
+        self.draw_slices(vertices, slice, width, depth, self.scale.x, self.scale.z, Y_POS, |px, py| {
+            [(ax + px) as f32, (y + depth_adjust) as f32, (az + depth - py + depth_adjust) as f32]
+        });
+    }
+
+    // length is along the slice's axis, the other axis is the region's size
+    pub fn draw_ui_three_slice(&self, vertices: &mut Vec<Vertex>, slice: &ThreeSlice, x: f64, y: f64, z: f64, length: f64, scale: f64) {
+        let (width, height) = slice.size_for(length, scale);
+        self.draw_ui_nine_slice(vertices, &slice.nine_slice(), x, y, z, width, height, scale);
+    }
+
+    pub fn draw_wall_three_slice(&self, vertices: &mut Vec<Vertex>, slice: &ThreeSlice, ax: f64, ay: f64, z: f64, length: f64, depth_adjust: f64) {
+        let scale_across = match slice.axis {
+            SliceAxis::Horizontal => self.scale.y,
+            SliceAxis::Vertical => self.scale.x,
+        };
+        let (width, height) = slice.size_for(length, scale_across);
+        self.draw_wall_nine_slice(vertices, &slice.nine_slice(), ax, ay, z, width, height, depth_adjust);
+    }
+
+    pub fn draw_floor_three_slice(&self, vertices: &mut Vec<Vertex>, slice: &ThreeSlice, ax: f64, y: f64, az: f64, length: f64, depth_adjust: f64) {
+        let scale_across = match slice.axis {
+            SliceAxis::Horizontal => self.scale.z,
+            SliceAxis::Vertical => self.scale.x,
+        };
+        let (width, depth) = slice.size_for(length, scale_across);
+        self.draw_floor_nine_slice(vertices, &slice.nine_slice(), ax, y, az, width, depth, depth_adjust);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stretched_middle() {
+        let segments = slice_segments(12, 4, 4, 100.0, 1.0, SliceMode::Stretch);
+        assert_eq!(segments.len(), 3);
+        assert_eq!((segments[1].from, segments[1].to), (4.0, 96.0));
+        assert_eq!((segments[1].texel_from, segments[1].texel_to), (4.0, 8.0));
+    }
+
+    #[test]
+    fn tiled_middle_cuts_the_last_tile() {
+        let segments = slice_segments(12, 4, 4, 18.0, 1.0, SliceMode::Tile);
+        // caps of 4, a middle of 10 from tiles of 4 -> 4, 4, 2
+        assert_eq!(segments.len(), 5);
+        assert_eq!((segments[3].from, segments[3].to), (12.0, 14.0));
+        assert_eq!((segments[3].texel_from, segments[3].texel_to), (4.0, 6.0));
+    }
+
+    #[test]
+    fn caps_shrink_when_too_small() {
+        let segments = slice_segments(12, 4, 4, 4.0, 1.0, SliceMode::Stretch);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].to, 2.0);
+    }
+}
@@ -0,0 +1,286 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::{self, Value};
+use aphid::{HashMap, Seconds};
+
+use {JamResult, JamError, load_file_contents};
+use render::texture_region::TextureRegion;
+use render::named_regions::{NamedRegions, sprite_name};
+
+// frames, durations and tags from an Aseprite or TexturePacker JSON export (hash or array layout)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteSheet {
+    pub name: String, // the sheet's sprite name, e.g. "characters/hero" for characters/hero.json
+    pub image: String, // sprite name of the image the frames are in
+    pub frames: Vec<SpriteFrame>, // in export order, tags index in to this
+    pub tags: Vec<FrameTag>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteFrame {
+    pub name: String,
+    pub region: TextureRegion,
+    pub duration: Option<Seconds>, // TexturePacker doesn't export durations
+    pub source_size: (u32, u32), // size before trimming
+    pub trim_offset: (u32, u32), // where the trimmed region sits within source_size, from the top left
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TagDirection {
+    Forward,
+    Reverse,
+    PingPong,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FrameTag {
+    pub name: String,
+    pub from: usize, // inclusive
+    pub to: usize, // inclusive
+    pub direction: TagDirection,
+}
+
+impl SpriteSheet {
+    pub fn tag(&self, name: &str) -> Option<&FrameTag> {
+        self.tags.iter().find(|t| t.name == name)
+    }
+
+    pub fn tag_frames(&self, name: &str) -> JamResult<&[SpriteFrame]> {
+        let tag = self.tag(name).ok_or_else(|| JamError::UnknownSpriteTag { sheet: self.name.clone(), tag: name.to_string() })?;
+        Ok(&self.frames[tag.from..(tag.to + 1)])
+    }
+
+    // "<sheet>/<frame index>" for each frame
+    pub fn named_regions(&self) -> Vec<(String, TextureRegion)> {
+        self.frames.iter().enumerate().map(|(i, f)| (format!("{}/{}", self.name, i), f.region)).collect()
+    }
+}
+
+#[derive(Deserialize, Debug, Copy, Clone)]
+struct JsonRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone)]
+struct JsonSize {
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct JsonFrame {
+    filename: Option<String>, // only in the array layout
+    frame: JsonRect,
+    #[serde(default)]
+    rotated: bool,
+    #[serde(rename = "spriteSourceSize")]
+    sprite_source_size: Option<JsonRect>,
+    #[serde(rename = "sourceSize")]
+    source_size: Option<JsonSize>,
+    duration: Option<u32>, // milliseconds
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct JsonTag {
+    name: String,
+    from: usize,
+    to: usize,
+    direction: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct JsonMeta {
+    image: Option<String>,
+    #[serde(rename = "frameTags", default)]
+    frame_tags: Vec<JsonTag>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct JsonSheet {
+    frames: Value, // an object (hash layout, order preserved) or an array
+    meta: JsonMeta,
+}
+
+fn sheet_error(path: &Path, message: String) -> JamError {
+    JamError::SpriteSheetError(path.to_path_buf(), message)
+}
+
+// regions are offset in to the region the sheet image was loaded as, so this works for packed directories too
+pub fn load_sprite_sheet(root: &Path, path: &Path, images: &NamedRegions) -> JamResult<SpriteSheet> {
+    let bytes = load_file_contents(path)?;
+    parse_sprite_sheet(root, path, &bytes, images)
+}
+
+pub fn parse_sprite_sheet(root: &Path, path: &Path, bytes: &[u8], images: &NamedRegions) -> JamResult<SpriteSheet> {
+    let sheet : JsonSheet = serde_json::from_slice(bytes)?;
+
+    let name = sprite_name(root, path);
+
+    let image_name = match sheet.meta.image {
+        Some(ref image) => {
+            let dir = path.parent().map(|p| p.to_path_buf()).unwrap_or(PathBuf::new());
+            sprite_name(root, &dir.join(image))
+        },
+        None => name.clone(),
+    };
+    let image_region = images.region(&image_name)?;
+
+    let json_frames : Vec<(String, JsonFrame)> = match sheet.frames {
+        Value::Object(ref map) => {
+            let mut frames = Vec::new();
+            for (key, value) in map.iter() {
+                let frame : JsonFrame = serde_json::from_value(value.clone())?;
+                frames.push((key.clone(), frame));
+            }
+            frames
+        },
+        Value::Array(ref values) => {
+            let mut frames = Vec::new();
+            for (i, value) in values.iter().enumerate() {
+                let frame : JsonFrame = serde_json::from_value(value.clone())?;
+                let frame_name = frame.filename.clone().unwrap_or_else(|| i.to_string());
+                frames.push((frame_name, frame));
+            }
+            frames
+        },
+        _ => return Err(sheet_error(path, "frames should be an object or an array".into())),
+    };
+
+    let mut frames = Vec::new();
+    for (frame_name, frame) in json_frames {
+        if frame.rotated {
+            return Err(sheet_error(path, format!("frame {:?} is rotated, disable rotation in the export", frame_name)));
+        }
+
+        let r = frame.frame;
+        if r.x + r.w > image_region.width() || r.y + r.h > image_region.height() {
+            return Err(sheet_error(path, format!("frame {:?} lies outside the image {:?}", frame_name, image_name)));
+        }
+
+        // json is top down, regions count v up from the bottom
+        let region = TextureRegion {
+            u_min: image_region.u_min + r.x,
+            u_max: image_region.u_min + r.x + r.w,
+            v_min: image_region.v_max - (r.y + r.h),
+            v_max: image_region.v_max - r.y,
+            layer: image_region.layer,
+            texture_width: image_region.texture_width,
+            texture_height: image_region.texture_height,
+        };
+
+        frames.push(SpriteFrame {
+            name: frame_name,
+            region: region,
+            duration: frame.duration.map(|ms| (ms as f64) / 1000.0),
+            source_size: frame.source_size.map(|s| (s.w, s.h)).unwrap_or((r.w, r.h)),
+            trim_offset: frame.sprite_source_size.map(|s| (s.x, s.y)).unwrap_or((0, 0)),
+        });
+    }
+
+    let mut tags = Vec::new();
+    for tag in sheet.meta.frame_tags {
+        if tag.from > tag.to || tag.to >= frames.len() {
+            return Err(sheet_error(path, format!("tag {:?} covers frames {}..{} but there are {}", tag.name, tag.from, tag.to, frames.len())));
+        }
+        let direction = match tag.direction.as_ref().map(|s| s.as_str()) {
+            Some("reverse") => TagDirection::Reverse,
+            Some("pingpong") => TagDirection::PingPong,
+            _ => TagDirection::Forward,
+        };
+        tags.push(FrameTag {
+            name: tag.name,
+            from: tag.from,
+            to: tag.to,
+            direction: direction,
+        });
+    }
+
+    Ok(SpriteSheet {
+        name: name,
+        image: image_name,
+        frames: frames,
+        tags: tags,
+    })
+}
+
+// loads every sheet, adding each frame's region to the lookup
+// a sheet that fails (or json that isn't a sheet at all) is logged and skipped rather than failing the texture load
+pub fn load_sprite_sheets(root: &Path, paths: &[PathBuf], regions: &mut NamedRegions) -> JamResult<HashMap<String, SpriteSheet>> {
+    let mut sheets = HashMap::default();
+    for path in paths {
+        let sheet = match load_sprite_sheet(root, path, regions) {
+            Ok(sheet) => sheet,
+            Err(e) => {
+                println!("skipping sprite sheet {:?} -> {:?}", path, e);
+                continue;
+            },
+        };
+        for (name, region) in sheet.named_regions() {
+            if regions.contains(&name) {
+                println!("sprite sheet {:?} frame {:?} has the same name as a texture, keeping the texture", path, name);
+                continue;
+            }
+            regions.insert(name, region);
+        }
+        sheets.insert(sheet.name.clone(), sheet);
+    }
+    Ok(sheets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn images() -> NamedRegions {
+        let mut regions = NamedRegions::empty();
+        regions.insert("hero".into(), TextureRegion { u_min: 0, u_max: 64, v_min: 0, v_max: 32, layer: 3, texture_width: 64, texture_height: 32 });
+        regions
+    }
+
+    #[test]
+    fn aseprite_hash_keeps_frame_order_and_tags() {
+        let json = br#"{
+            "frames": {
+                "hero 1.aseprite": { "frame": { "x": 32, "y": 0, "w": 32, "h": 16 }, "rotated": false, "trimmed": false, "duration": 200 },
+                "hero 0.aseprite": { "frame": { "x": 0, "y": 16, "w": 32, "h": 16 }, "rotated": false, "trimmed": false, "duration": 100 }
+            },
+            "meta": { "image": "hero.png", "frameTags": [ { "name": "walk", "from": 0, "to": 1, "direction": "pingpong" } ] }
+        }"#;
+        let sheet = parse_sprite_sheet(Path::new("textures"), Path::new("textures/hero.json"), json, &images()).unwrap();
+
+        assert_eq!(sheet.image, "hero");
+        assert_eq!(sheet.frames[0].name, "hero 1.aseprite");
+        assert_eq!(sheet.frames[0].duration, Some(0.2));
+        assert_eq!((sheet.frames[0].region.v_min, sheet.frames[0].region.v_max), (16, 32));
+        assert_eq!((sheet.frames[1].region.v_min, sheet.frames[1].region.v_max), (0, 16));
+        assert_eq!(sheet.frames[1].region.layer, 3);
+        assert_eq!(sheet.tag("walk").map(|t| t.direction), Some(TagDirection::PingPong));
+        assert_eq!(sheet.tag_frames("walk").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn texture_packer_array_rejects_rotation() {
+        let json = br#"{
+            "frames": [ { "filename": "a.png", "frame": { "x": 0, "y": 0, "w": 8, "h": 8 }, "rotated": true } ],
+            "meta": { "image": "hero.png" }
+        }"#;
+        assert!(parse_sprite_sheet(Path::new("textures"), Path::new("textures/hero.json"), json, &images()).is_err());
+    }
+
+    #[test]
+    fn bad_json_is_skipped() {
+        use test_support::TempDir;
+
+        let root = TempDir::new("sprite_sheet_skip");
+        let good = root.write("hero.json", br#"{ "frames": [ { "filename": "a", "frame": { "x": 0, "y": 0, "w": 8, "h": 8 } } ], "meta": { "image": "hero.png" } }"#);
+        let bad = root.write("particles.json", br#"{ "emitters": "#);
+
+        let mut regions = images();
+        let sheets = load_sprite_sheets(root.path(), &[bad, good], &mut regions).unwrap();
+        assert_eq!(sheets.len(), 1);
+        assert!(sheets.contains_key("hero"));
+    }
+}
@@ -2,17 +2,30 @@ use std::fs;
 use std::path::{Path,PathBuf};
 
 use image;
-use image::{GenericImage, RgbaImage};
+use image::{GenericImage, RgbaImage, Rgba};
 use std::fmt;
-use aphid::HashSet;
+use aphid::{HashMap, HashSet};
 
 use JamResult;
 use JamError;
+use render::loader::LoadProgress;
+use render::packer::{PackerConfig, pack_images};
+use render::texture_region::TextureRegion;
+use render::named_regions::{NamedRegions, sprite_name};
+use render::sprite_sheet::{SpriteSheet, load_sprite_sheets};
+use render::aseprite::{AsepriteFile, is_aseprite, load_aseprite, frame_name};
 
-#[derive(Debug)]
+pub const SPRITE_SHEET_EXTENSION : &'static str = "json";
+
+// a solid white region every load reserves, for untextured geometry (shapes etc.) drawn with the primary texture
+pub const WHITE_REGION : &'static str = "white";
+const WHITE_SIZE : u32 = 4; // when packed, the packer extrudes it so filtering stays white
+
+#[derive(Debug, Clone)]
 pub struct TextureDirectory {
     pub path: PathBuf, 
     pub extensions: HashSet<String>,
+    pub packing: Option<PackerConfig>, // None -> one image per layer, all the same size
 }
 
 impl TextureDirectory {
@@ -20,57 +33,178 @@ impl TextureDirectory {
         TextureDirectory {
             path: PathBuf::from(path), // convert to absolute here?
             extensions: extensions.iter().map(|s| s.to_lowercase()).collect(),
+            packing: None,
+        }
+    }
+
+    // mixed size images are bin packed in to layers of the given config
+    pub fn with_packing(self, config: PackerConfig) -> TextureDirectory {
+        TextureDirectory {
+            packing: Some(config),
+            .. self
+        }
+    }
+
+    // every file in the directory (and sub directories) with a matching extension, plus aseprite files, sorted
+    pub fn texture_paths(&self) -> JamResult<Vec<PathBuf>> {
+        let mut paths = try!(read_directory_paths_recursive(&self.path));
+        paths.sort();
+
+        Ok(paths.into_iter().filter(|path| {
+            is_aseprite(path) || path.extension().and_then(|p| p.to_str()).map(|s| self.extensions.contains(&s.to_lowercase())).unwrap_or(false)
+        }).collect())
+    }
+
+    // Aseprite/TexturePacker json exported alongside the images
+    pub fn sheet_paths(&self) -> JamResult<Vec<PathBuf>> {
+        let mut paths = try!(read_directory_paths_recursive(&self.path));
+        paths.sort();
+
+        Ok(paths.into_iter().filter(|path| is_sprite_sheet(path)).collect())
+    }
+
+    // adds each sheet's frames to regions, regions must already hold the sheet images
+    pub fn load_sheets(&self, regions: &mut NamedRegions) -> JamResult<HashMap<String, SpriteSheet>> {
+        let paths = try!(self.sheet_paths());
+        load_sprite_sheets(&self.path, &paths, regions)
+    }
+
+    // json sheets layered over the image and aseprite regions, a sheet reload starts again from those
+    pub fn load_sheets_over(&self, image_regions: &NamedRegions, animations: &HashMap<String, SpriteSheet>) -> JamResult<(NamedRegions, HashMap<String, SpriteSheet>)> {
+        let mut regions = image_regions.clone();
+        let mut sheets = try!(self.load_sheets(&mut regions));
+        for (name, sheet) in animations {
+            if sheets.contains_key(name) {
+                println!("aseprite file {:?} replaces the sprite sheet json of the same name", name);
+            }
+            sheets.insert(name.clone(), sheet.clone());
         }
+        Ok((regions, sheets))
     }
 
     pub fn load(&self) -> JamResult<TextureArrayData> {
+        self.load_reporting(&LoadProgress::new())
+    }
+
+    // as load, counting each decoded file in to progress
+    pub fn load_reporting(&self, progress: &LoadProgress) -> JamResult<TextureArrayData> {
         let mut images : Vec<RgbaImage> = Vec::new();
+        let mut names : Vec<String> = Vec::new();
+        let mut layer_paths : Vec<PathBuf> = Vec::new();
+        let mut animations : Vec<(String, AsepriteFile)> = Vec::new();
 
         let mut dimensions : Option<Dimensions> = None;
 
-        let mut paths = try!(read_directory_paths(&self.path));
-        paths.sort();
+        let paths = try!(self.texture_paths());
+        progress.set_total(paths.len());
+
+        if let Some(config) = self.packing {
+            return self.load_packed(&paths, &config, progress);
+        }
 
         println!("sorted paths -> {:?}", paths);
 
-        for path in paths {
-            if let Some(extension) = path.extension().and_then(|p| p.to_str()).map(|s|s.to_lowercase()) {
-                // let ext : String = extension.into();
-                if self.extensions.contains(&extension) {
-                    println!("path -> {:?} with extension -> {:?}", path, extension);
-                    let img = try!(image::open(path.clone()));
-
-                    let d = img.dimensions();
-                    let w = d.0 as u32;
-                    let h = d.1 as u32;
-
-                    if let Some(ed) = dimensions {
-                        if ed != (w, h) {
-                            return Err(JamError::MismatchingDimensions);
-                        }
-                    } else {
-                        dimensions = Some((w, h));
-                    }
+        let mut sources : HashMap<String, PathBuf> = HashMap::default();
+
+        for path in &paths {
+            println!("path -> {:?}", path);
+            let name = sprite_name(&self.path, path);
+
+            // aseprite frames take a layer each
+            let layer_images = if is_aseprite(path) {
+                let ase = try!(load_aseprite(path));
+                let frames : Vec<(String, RgbaImage)> = ase.frames.iter().enumerate().map(|(i, f)| (frame_name(&name, i), f.image.clone())).collect();
+                // the bare name refers to the first frame
+                try!(claim_name(&mut sources, &name, path));
+                animations.push((name, ase));
+                frames
+            } else {
+                vec![(name, try!(load_image(path)))]
+            };
+
+            for (layer_name, img) in layer_images {
+                try!(claim_name(&mut sources, &layer_name, path));
 
-                    images.push(img.to_rgba());
+                let d = img.dimensions();
+
+                if let Some(ed) = dimensions {
+                    if ed != d {
+                        return Err(JamError::MismatchingDimensions);
+                    }
+                } else {
+                    dimensions = Some(d);
                 }
+
+                images.push(img);
+                names.push(layer_name);
+                layer_paths.push(path.clone());
             }
+            progress.increment();
         }
 
         if let Some((w, h))  = dimensions {
+            // a whole layer when unpacked, it comes last and has no path so hot reloading ignores it
+            try!(reserve_white(&sources));
+            images.push(white_image(w, h));
+            names.push(WHITE_REGION.to_string());
+
+            let mut image_regions = layer_regions(&names, w, h);
+            let animations = add_animations(animations, &mut image_regions);
+            let (regions, sheets) = try!(self.load_sheets_over(&image_regions, &animations));
             Ok(TextureArrayData {
                 dimensions: TextureArrayDimensions { 
                     width: w,
                     height: h,
                     layers: images.len() as u32,
                 },
+                regions: regions,
+                image_regions: image_regions,
+                sheets: sheets,
+                animations: animations,
                 images: images,
+                paths: layer_paths,
             })
         } else {
             Err(JamError::NoFiles)
         }    
     }
 
+    fn load_packed(&self, paths: &[PathBuf], config: &PackerConfig, progress: &LoadProgress) -> JamResult<TextureArrayData> {
+        let mut images : Vec<(String, RgbaImage)> = Vec::new();
+        let mut animations : Vec<(String, AsepriteFile)> = Vec::new();
+        let mut sources : HashMap<String, PathBuf> = HashMap::default();
+        for path in paths {
+            let name = sprite_name(&self.path, path);
+            try!(claim_name(&mut sources, &name, path));
+            if is_aseprite(path) {
+                let ase = try!(load_aseprite(path));
+                for (i, frame) in ase.frames.iter().enumerate() {
+                    let frame_name = frame_name(&name, i);
+                    try!(claim_name(&mut sources, &frame_name, path));
+                    images.push((frame_name, frame.image.clone()));
+                }
+                animations.push((name, ase));
+            } else {
+                images.push((name, try!(load_image(path))));
+            }
+            progress.increment();
+        }
+        if !images.is_empty() {
+            try!(reserve_white(&sources));
+            images.push((WHITE_REGION.to_string(), white_image(WHITE_SIZE, WHITE_SIZE)));
+        }
+        let mut data = try!(pack_images(images, config));
+        let animations = add_animations(animations, &mut data.regions);
+        let (regions, sheets) = try!(self.load_sheets_over(&data.regions, &animations));
+        Ok(TextureArrayData {
+            regions: regions,
+            image_regions: data.regions,
+            sheets: sheets,
+            animations: animations,
+            .. data
+        })
+    }
+
     pub fn contains(&self, path:&Path) -> bool {
         use std::path;
         let my_components : Vec<path::Component> = self.path.components().collect();
@@ -82,6 +216,64 @@ impl TextureDirectory {
     }
 }
 
+// two files (or an aseprite frame and a file) with the same sprite name would silently replace each other
+fn claim_name(sources: &mut HashMap<String, PathBuf>, name: &str, path: &Path) -> JamResult<()> {
+    if let Some(first) = sources.get(name) {
+        return Err(JamError::DuplicateSpriteName { name: name.to_string(), first: first.clone(), second: path.to_path_buf() });
+    }
+    sources.insert(name.to_string(), path.to_path_buf());
+    Ok(())
+}
+
+fn reserve_white(sources: &HashMap<String, PathBuf>) -> JamResult<()> {
+    match sources.get(WHITE_REGION) {
+        Some(path) => Err(JamError::DuplicateSpriteName { name: WHITE_REGION.to_string(), first: path.clone(), second: PathBuf::from("(reserved)") }),
+        None => Ok(()),
+    }
+}
+
+fn white_image(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_pixel(width, height, Rgba { data: [255, 255, 255, 255] })
+}
+
+// whole layer regions for unpacked directories
+fn layer_regions(names: &[String], width: u32, height: u32) -> NamedRegions {
+    NamedRegions::new(names.iter().enumerate().map(|(layer, name)| {
+        let region = TextureRegion {
+            u_min: 0,
+            u_max: width,
+            v_min: 0,
+            v_max: height,
+            layer: layer as u32,
+            texture_width: width,
+            texture_height: height,
+        };
+        (name.clone(), region)
+    }).collect())
+}
+
+// a sheet per aseprite file, the bare sprite name refers to the first frame
+fn add_animations(animations: Vec<(String, AsepriteFile)>, regions: &mut NamedRegions) -> HashMap<String, SpriteSheet> {
+    let mut sheets : HashMap<String, SpriteSheet> = HashMap::default();
+    for (name, ase) in animations {
+        let frame_regions : Vec<TextureRegion> = (0..ase.frames.len()).filter_map(|i| regions.get(&frame_name(&name, i))).collect();
+        if let Some(first) = frame_regions.first() {
+            regions.insert(name.clone(), *first);
+        }
+        sheets.insert(name.clone(), ase.sprite_sheet(&name, &frame_regions));
+    }
+    sheets
+}
+
+pub fn is_sprite_sheet(path: &Path) -> bool {
+    path.extension().and_then(|p| p.to_str()).map(|s| s.to_lowercase() == SPRITE_SHEET_EXTENSION).unwrap_or(false)
+}
+
+pub fn load_image(path:&Path) -> JamResult<RgbaImage> {
+    let img = try!(image::open(path));
+    Ok(img.to_rgba())
+}
+
 pub fn read_directory_paths(path:&Path) -> JamResult<Vec<PathBuf>> {
     let mut paths : Vec<PathBuf> = Vec::new();
 
@@ -94,6 +286,20 @@ pub fn read_directory_paths(path:&Path) -> JamResult<Vec<PathBuf>> {
     Ok(paths)
 }
 
+pub fn read_directory_paths_recursive(path:&Path) -> JamResult<Vec<PathBuf>> {
+    let mut paths : Vec<PathBuf> = Vec::new();
+
+    for entry_path in try!(read_directory_paths(path)) {
+        if entry_path.is_dir() {
+            paths.extend(try!(read_directory_paths_recursive(&entry_path)));
+        } else {
+            paths.push(entry_path);
+        }
+    }
+
+    Ok(paths)
+}
+
 type Dimensions = (u32, u32); // rename this as TextureDimensions?
 
 // hrm, we currently load it all in to ram in uncompressed form :-/ zero reason why this isn't streamed in as a whole
@@ -101,6 +307,11 @@ type Dimensions = (u32, u32); // rename this as TextureDimensions?
 pub struct TextureArrayData {
     pub dimensions : TextureArrayDimensions,
     pub images: Vec<RgbaImage>,
+    pub paths: Vec<PathBuf>, // the file each image came from (aseprite files repeat per frame, the white layer has none), empty when packed
+    pub regions: NamedRegions,
+    pub image_regions: NamedRegions, // regions before any sprite sheet json was applied
+    pub sheets: HashMap<String, SpriteSheet>, // by sheet name
+    pub animations: HashMap<String, SpriteSheet>, // the aseprite files' sheets, also in sheets
 }
 
 impl fmt::Debug for TextureArrayData {
@@ -114,4 +325,46 @@ pub struct TextureArrayDimensions {
     pub width: u32,
     pub height: u32,
     pub layers: u32,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_names_name_both_files() {
+        let mut sources : HashMap<String, PathBuf> = HashMap::default();
+        claim_name(&mut sources, "hero", Path::new("sprites/hero.png")).unwrap();
+        claim_name(&mut sources, "hero/0", Path::new("sprites/hero/0.png")).unwrap();
+        match claim_name(&mut sources, "hero", Path::new("sprites/hero.aseprite")) {
+            Err(JamError::DuplicateSpriteName { name, first, second }) => {
+                assert_eq!(name, "hero");
+                assert_eq!(first, PathBuf::from("sprites/hero.png"));
+                assert_eq!(second, PathBuf::from("sprites/hero.aseprite"));
+            },
+            other => panic!("expected a duplicate, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn white_is_reserved_packed_or_not() {
+        use test_support::TempDir;
+
+        let dir = TempDir::new("texture_array_white");
+        RgbaImage::from_pixel(16, 16, Rgba { data: [255, 0, 0, 255] }).save(dir.path().join("hero.png")).unwrap();
+        let mut extensions : HashSet<String> = HashSet::default();
+        extensions.insert("png".into());
+        let directory = TextureDirectory::for_path(dir.path().to_str().unwrap(), extensions);
+
+        let unpacked = directory.load().unwrap();
+        let white = unpacked.regions.region(WHITE_REGION).unwrap();
+        assert_eq!(unpacked.dimensions.layers, 2);
+        assert_eq!(unpacked.images[white.layer as usize].get_pixel(8, 8).data, [255, 255, 255, 255]);
+        assert_eq!(unpacked.paths.len(), 1);
+
+        let packed = directory.with_packing(PackerConfig::square(64)).load().unwrap();
+        let white = packed.regions.region(WHITE_REGION).unwrap();
+        let layer = &packed.images[white.layer as usize];
+        // v counts up from the bottom
+        assert_eq!(layer.get_pixel(white.u_min, white.texture_height - white.v_max).data, [255, 255, 255, 255]);
+    }
+}
@@ -7,7 +7,8 @@ pub struct TextureRegion {
     pub v_min: u32,
     pub v_max: u32,
     pub layer: u32,
-    pub texture_size: u32,
+    pub texture_width: u32,
+    pub texture_height: u32,
 }
 
 impl TextureRegion {
@@ -34,7 +35,8 @@ impl TextureRegion {
             v_min: self.v_min,
             v_max: self.v_max,
             layer: self.layer,
-            texture_size: self.texture_size,
+            texture_width: self.texture_width,
+            texture_height: self.texture_height,
         }
     }
 
@@ -45,7 +47,8 @@ impl TextureRegion {
             v_min: self.v_max,
             v_max: self.v_min,
             layer: self.layer,
-            texture_size: self.texture_size,
+            texture_width: self.texture_width,
+            texture_height: self.texture_height,
         }
     }
 
@@ -66,19 +69,19 @@ impl TextureRegion {
     }
 
     pub fn nu_min(&self) -> f32 {
-         (self.u_min as f32) / (self.texture_size as f32)
+         (self.u_min as f32) / (self.texture_width as f32)
     }
 
     pub fn nu_max(&self) -> f32 {
-        (self.u_max as f32) / (self.texture_size as f32)
+        (self.u_max as f32) / (self.texture_width as f32)
     }
 
     pub fn nv_min(&self) -> f32 {
-        (self.v_min as f32) / (self.texture_size as f32)
+        (self.v_min as f32) / (self.texture_height as f32)
     }
 
     pub fn nv_max(&self) -> f32 {
-        (self.v_max as f32) / (self.texture_size as f32)
+        (self.v_max as f32) / (self.texture_height as f32)
     }
 
     pub fn nu_mid(&self) -> f32 {
@@ -90,17 +93,18 @@ impl TextureRegion {
     }
 
     pub fn n_width(&self) -> f32 {
-        (self.width() as f32) / (self.texture_size as f32)
+        (self.width() as f32) / (self.texture_width as f32)
     }
 
     pub fn n_height(&self) -> f32 {
-        (self.height() as f32) / (self.texture_size as f32)
+        (self.height() as f32) / (self.texture_height as f32)
     }
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
 pub struct TextureAtlas {
-    pub texture_size: u32,
+    pub texture_width: u32,
+    pub texture_height: u32,
     pub tile_size: u32,
 }
 
@@ -108,7 +112,8 @@ impl TextureAtlas {
     pub fn layer(&self, layer: u32) -> TextureAtlasLayer {
         TextureAtlasLayer {
             tile_size: self.tile_size,
-            texture_size: self.texture_size,
+            texture_width: self.texture_width,
+            texture_height: self.texture_height,
             layer,
         }
     }
@@ -116,7 +121,8 @@ impl TextureAtlas {
 
 pub struct TextureAtlasLayer {
     pub tile_size: u32,
-    pub texture_size: u32,
+    pub texture_width: u32,
+    pub texture_height: u32,
     pub layer: u32,
 }
 
@@ -142,7 +148,8 @@ impl TextureAtlas {
             v_min: v_min,
             v_max: v_max,
             layer: 0,
-            texture_size: self.texture_size,
+            texture_width: self.texture_width,
+            texture_height: self.texture_height,
         }
     }
 
@@ -155,7 +162,8 @@ impl TextureAtlas {
             v_min: v_min,
             v_max: v_max,
             layer: 0,
-            texture_size: self.texture_size,
+            texture_width: self.texture_width,
+            texture_height: self.texture_height,
         }
     }
 }
@@ -172,7 +180,8 @@ impl TextureAtlasLayer {
             v_min: v_min,
             v_max: v_max,
             layer: self.layer,
-            texture_size: self.texture_size,
+            texture_width: self.texture_width,
+            texture_height: self.texture_height,
         }
     }
 
@@ -186,7 +195,25 @@ impl TextureAtlasLayer {
             v_min: v_min,
             v_max: v_max,
             layer: self.layer,
-            texture_size: self.texture_size,
+            texture_width: self.texture_width,
+            texture_height: self.texture_height,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_square_textures_normalize_v_by_height() {
+        let atlas = TextureAtlas { texture_width: 256, texture_height: 64, tile_size: 16 };
+        let region = atlas.layer(2).get(1, 2, 2, 1);
+
+        assert_eq!((region.nu_min(), region.nu_max()), (16.0 / 256.0, 48.0 / 256.0));
+        assert_eq!((region.nv_min(), region.nv_max()), (32.0 / 64.0, 48.0 / 64.0));
+        assert_eq!((region.n_width(), region.n_height()), (32.0 / 256.0, 16.0 / 64.0));
+        assert_eq!(region.v_flip().nv_min(), 48.0 / 64.0);
+        assert_eq!(region.layer, 2);
+    }
+}
@@ -0,0 +1,664 @@
+use std::io::Read;
+use std::path::{Path, PathBuf, Component};
+
+use xml::reader::{EventReader, XmlEvent};
+use base64;
+use flate2::read::{ZlibDecoder, GzDecoder};
+use aphid::HashMap;
+
+use {JamResult, JamError, load_file_contents};
+use render::texture_region::TextureRegion;
+use render::tilemap::{Tile, Tilemap};
+use render::named_regions::{NamedRegions, sprite_name};
+use render::any_changed;
+
+// the top bits of a gid are flip flags
+pub const FLIPPED_HORIZONTALLY : u32 = 0x80000000;
+pub const FLIPPED_VERTICALLY : u32 = 0x40000000;
+pub const FLIPPED_DIAGONALLY : u32 = 0x20000000;
+const GID_MASK : u32 = !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Color(String), // #AARRGGBB as written by Tiled
+    File(String),
+}
+
+pub type Properties = HashMap<String, PropertyValue>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TilesetImage {
+    pub source: PathBuf, // resolved against the tmx/tsx it was referenced from
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tileset {
+    pub first_gid: u32,
+    pub name: String,
+    pub source: Option<PathBuf>, // the tsx, None when embedded in the map
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub spacing: u32,
+    pub margin: u32,
+    pub columns: u32,
+    pub tile_count: u32,
+    pub image: Option<TilesetImage>, // None for image collection tilesets, which aren't supported
+    pub tile_properties: HashMap<u32, Properties>, // by local tile id
+}
+
+impl Tileset {
+    pub fn contains_gid(&self, gid: u32) -> bool {
+        gid >= self.first_gid && gid < self.first_gid + self.tile_count
+    }
+
+    // pixel rect (x, y from the top left, w, h) of a local tile id within the image
+    pub fn tile_rect(&self, id: u32) -> (u32, u32, u32, u32) {
+        let columns = self.columns.max(1);
+        let x = self.margin + (id % columns) * (self.tile_width + self.spacing);
+        let y = self.margin + (id / columns) * (self.tile_height + self.spacing);
+        (x, y, self.tile_width, self.tile_height)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledTileLayer {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub visible: bool,
+    pub opacity: f64,
+    pub gids: Vec<u32>, // row major from the top left, flip flags included, 0 is empty
+    pub properties: Properties,
+}
+
+impl TiledTileLayer {
+    pub fn gid(&self, x: u32, y: u32) -> u32 {
+        if x < self.width && y < self.height {
+            self.gids[(y * self.width + x) as usize]
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectShape {
+    Rectangle,
+    Ellipse,
+    Point,
+    Polygon(Vec<(f64, f64)>), // relative to the object's x/y
+    Polyline(Vec<(f64, f64)>),
+}
+
+// positions are Tiled pixels, y down
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledObject {
+    pub id: u32,
+    pub name: String,
+    pub kind: String, // Tiled's "type"
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub rotation: f64, // degrees clockwise
+    pub gid: Option<u32>, // tile objects
+    pub visible: bool,
+    pub shape: ObjectShape,
+    pub properties: Properties,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectLayer {
+    pub name: String,
+    pub visible: bool,
+    pub objects: Vec<TiledObject>,
+    pub properties: Properties,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledMap {
+    pub path: PathBuf,
+    pub width: u32, // in tiles
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tilesets: Vec<Tileset>,
+    pub tile_layers: Vec<TiledTileLayer>, // group layers are flattened in to these two
+    pub object_layers: Vec<ObjectLayer>,
+    pub properties: Properties,
+}
+
+impl TiledMap {
+    pub fn tile_layer(&self, name: &str) -> Option<&TiledTileLayer> {
+        self.tile_layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn object_layer(&self, name: &str) -> Option<&ObjectLayer> {
+        self.object_layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn tileset_for_gid(&self, gid: u32) -> Option<&Tileset> {
+        let gid = gid & GID_MASK;
+        self.tilesets.iter().find(|t| t.contains_gid(gid))
+    }
+
+    // tileset images must live in the texture directory, they're looked up by sprite name and never loaded from the map
+    pub fn tileset_image_names(&self, texture_root: &Path) -> Vec<String> {
+        self.tilesets.iter().filter_map(|t| t.image.as_ref()).map(|i| sprite_name(texture_root, &i.source)).collect()
+    }
+
+    // None for empty cells
+    pub fn region_for_gid(&self, gid: u32, texture_root: &Path, images: &NamedRegions) -> JamResult<Option<TextureRegion>> {
+        let id = gid & GID_MASK;
+        if id == 0 {
+            return Ok(None);
+        }
+        let tileset = self.tileset_for_gid(id).ok_or_else(|| JamError::TiledError(self.path.clone(), format!("no tileset for gid {}", id)))?;
+        let image = tileset.image.as_ref().ok_or_else(|| JamError::TiledError(self.path.clone(), format!("tileset {:?} has no image", tileset.name)))?;
+        let image_region = images.region(&sprite_name(texture_root, &image.source))?;
+
+        let (x, y, w, h) = tileset.tile_rect(id - tileset.first_gid);
+        if x + w > image_region.width() || y + h > image_region.height() {
+            return Err(JamError::TiledError(self.path.clone(), format!("gid {} is outside {:?}, the tileset expects a larger image", id, image.source)));
+        }
+
+        // offset in to wherever the image ended up, v counts up from the bottom
+        let region = TextureRegion {
+            u_min: image_region.u_min + x,
+            u_max: image_region.u_min + x + w,
+            v_min: image_region.v_max - (y + h),
+            v_max: image_region.v_max - y,
+            layer: image_region.layer,
+            texture_width: image_region.texture_width,
+            texture_height: image_region.texture_height,
+        };
+
+        // diagonal flips (rotated tiles) aren't representable by a region
+        Ok(Some(region.h_flipped(gid & FLIPPED_HORIZONTALLY != 0).v_flipped(gid & FLIPPED_VERTICALLY != 0)))
+    }
+
+    // a region per cell of the layer, row major from the top left
+    pub fn layer_regions(&self, layer: &TiledTileLayer, texture_root: &Path, images: &NamedRegions) -> JamResult<Vec<Option<TextureRegion>>> {
+        let mut regions = Vec::with_capacity(layer.gids.len());
+        for &gid in &layer.gids {
+            regions.push(self.region_for_gid(gid, texture_root, images)?);
+        }
+        Ok(regions)
+    }
+
+    // copies a layer in to a Tilemap layer, tiled rows become z
+    // tileset images aren't loaded from here, they must already be in the texture directory (see tileset_image_names),
+    // packed on the tilemap layer's texture layer and aligned to its atlas, anything else is an error
+    // Tilemap tiles can't flip, flipped cells are placed unflipped with a warning
+    pub fn fill_tilemap(&self, layer: &TiledTileLayer, tilemap: &mut Tilemap, tilemap_layer: usize, texture_root: &Path, images: &NamedRegions) -> JamResult<()> {
+        let texture_layer = match tilemap.layers.get(tilemap_layer) {
+            Some(l) => l.texture_layer,
+            None => return Err(JamError::TiledError(self.path.clone(), format!("tilemap has no layer {} to fill from {:?}", tilemap_layer, layer.name))),
+        };
+        let atlas = tilemap.atlas;
+        let tile_size = atlas.tile_size;
+
+        let mut flipped = 0;
+        for y in 0..layer.height.min(tilemap.depth) {
+            for x in 0..layer.width.min(tilemap.width) {
+                let gid = layer.gid(x, y);
+                if gid & !GID_MASK != 0 {
+                    flipped += 1;
+                }
+                let tile = match self.region_for_gid(gid & GID_MASK, texture_root, images)? {
+                    Some(region) => {
+                        if region.layer != texture_layer || region.texture_width != atlas.texture_width || region.texture_height != atlas.texture_height {
+                            return Err(JamError::TiledError(self.path.clone(), format!("tile at {}, {} of {:?} is on texture layer {} ({}x{}), the tilemap layer draws layer {} ({}x{})",
+                                x, y, layer.name, region.layer, region.texture_width, region.texture_height, texture_layer, atlas.texture_width, atlas.texture_height)));
+                        }
+                        if region.width() != tile_size || region.height() != tile_size || region.u_min % tile_size != 0 || region.v_min % tile_size != 0 {
+                            return Err(JamError::TiledError(self.path.clone(), format!("tile at {}, {} of {:?} isn't on a {} pixel grid", x, y, layer.name, tile_size)));
+                        }
+                        Some(Tile { u: region.u_min / tile_size, v: region.v_min / tile_size })
+                    },
+                    None => None,
+                };
+                tilemap.set(tilemap_layer, x, y, tile);
+            }
+        }
+
+        if flipped > 0 {
+            println!("tiled map {:?} layer {:?} -> {} flipped tiles placed unflipped", self.path, layer.name, flipped);
+        }
+        Ok(())
+    }
+
+    // the map file and any external tilesets
+    pub fn source_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.path.clone()];
+        paths.extend(self.tilesets.iter().filter_map(|t| t.source.clone()));
+        paths
+    }
+}
+
+// a loaded map that reloads itself when the renderer reports its files changed
+pub struct TiledMapFile {
+    pub path: PathBuf,
+    pub map: TiledMap,
+}
+
+impl TiledMapFile {
+    pub fn load(path: &Path) -> JamResult<TiledMapFile> {
+        Ok(TiledMapFile {
+            path: path.to_path_buf(),
+            map: load_tmx(path)?,
+        })
+    }
+
+    // true when the map was reloaded, a failed reload keeps the previous map
+    pub fn reload_if_changed(&mut self, changed: &[PathBuf]) -> JamResult<bool> {
+        let affected = any_changed(changed, &self.map.source_paths());
+        if affected {
+            self.map = load_tmx(&self.path)?;
+        }
+        Ok(affected)
+    }
+}
+
+// "maps" + "../textures/ground.png" -> "textures/ground.png", so sprite names can be taken relative to the texture root
+fn resolve(dir: &Path, source: &str) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in dir.join(source).components() {
+        match component {
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    resolved.push("..");
+                }
+            },
+            Component::CurDir => (),
+            other => resolved.push(other.as_os_str()),
+        }
+    }
+    resolved
+}
+
+// just enough of a DOM to walk
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|s| s.as_str())
+    }
+
+    fn attr_u32(&self, name: &str) -> Result<u32, String> {
+        self.attr(name).unwrap_or("0").trim().parse::<u32>().map_err(|e| format!("{} on <{}> -> {:?}", name, self.name, e))
+    }
+
+    fn attr_f64(&self, name: &str) -> Result<f64, String> {
+        self.attr(name).unwrap_or("0").trim().parse::<f64>().map_err(|e| format!("{} on <{}> -> {:?}", name, self.name, e))
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+}
+
+fn parse_xml(bytes: &[u8]) -> Result<Element, String> {
+    let mut stack : Vec<Element> = Vec::new();
+
+    for event in EventReader::new(bytes) {
+        match event.map_err(|e| format!("{}", e))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes.into_iter().map(|a| (a.name.local_name, a.value)).collect(),
+                    children: Vec::new(),
+                    text: String::new(),
+                });
+            },
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().ok_or("unbalanced xml")?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(current) = stack.last_mut() {
+                    current.text.push_str(&text);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Err("no root element".into())
+}
+
+pub fn load_tmx(path: &Path) -> JamResult<TiledMap> {
+    let bytes = load_file_contents(path)?;
+    parse_tmx(path, &bytes)
+}
+
+pub fn load_tsx(path: &Path, first_gid: u32) -> JamResult<Tileset> {
+    let bytes = load_file_contents(path)?;
+    let root = parse_xml(&bytes).map_err(|e| JamError::TiledError(path.to_path_buf(), e))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut tileset = parse_tileset(&root, first_gid, dir).map_err(|e| JamError::TiledError(path.to_path_buf(), e))?;
+    tileset.source = Some(path.to_path_buf());
+    Ok(tileset)
+}
+
+// external tilesets are loaded relative to path
+pub fn parse_tmx(path: &Path, bytes: &[u8]) -> JamResult<TiledMap> {
+    let error = |e: String| JamError::TiledError(path.to_path_buf(), e);
+
+    let root = parse_xml(bytes).map_err(&error)?;
+    if root.name != "map" {
+        return Err(error(format!("expected <map> but found <{}>", root.name)));
+    }
+    if root.attr("infinite") == Some("1") {
+        return Err(error("infinite maps aren't supported".into()));
+    }
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut map = TiledMap {
+        path: path.to_path_buf(),
+        width: root.attr_u32("width").map_err(&error)?,
+        height: root.attr_u32("height").map_err(&error)?,
+        tile_width: root.attr_u32("tilewidth").map_err(&error)?,
+        tile_height: root.attr_u32("tileheight").map_err(&error)?,
+        tilesets: Vec::new(),
+        tile_layers: Vec::new(),
+        object_layers: Vec::new(),
+        properties: parse_properties(&root).map_err(&error)?,
+    };
+
+    for child in &root.children {
+        if child.name == "tileset" {
+            let first_gid = child.attr_u32("firstgid").map_err(&error)?;
+            let tileset = match child.attr("source") {
+                Some(source) => load_tsx(&resolve(dir, source), first_gid)?,
+                None => parse_tileset(child, first_gid, dir).map_err(&error)?,
+            };
+            map.tilesets.push(tileset);
+        }
+    }
+
+    parse_layers(&root, &mut map).map_err(&error)?;
+
+    Ok(map)
+}
+
+fn parse_layers(parent: &Element, map: &mut TiledMap) -> Result<(), String> {
+    for child in &parent.children {
+        match child.name.as_str() {
+            "layer" => {
+                let width = child.attr_u32("width")?;
+                let height = child.attr_u32("height")?;
+                let data = child.child("data").ok_or("layer without data")?;
+                let gids = parse_layer_data(data)?;
+                if gids.len() != (width * height) as usize {
+                    return Err(format!("layer {:?} has {} tiles but should have {}", child.attr("name"), gids.len(), width * height));
+                }
+                map.tile_layers.push(TiledTileLayer {
+                    name: child.attr("name").unwrap_or("").to_string(),
+                    width: width,
+                    height: height,
+                    visible: child.attr("visible") != Some("0"),
+                    opacity: child.attr("opacity").map(|_| child.attr_f64("opacity")).unwrap_or(Ok(1.0))?,
+                    gids: gids,
+                    properties: parse_properties(child)?,
+                });
+            },
+            "objectgroup" => {
+                let mut objects = Vec::new();
+                for object in child.children.iter().filter(|c| c.name == "object") {
+                    objects.push(parse_object(object)?);
+                }
+                map.object_layers.push(ObjectLayer {
+                    name: child.attr("name").unwrap_or("").to_string(),
+                    visible: child.attr("visible") != Some("0"),
+                    objects: objects,
+                    properties: parse_properties(child)?,
+                });
+            },
+            "group" => parse_layers(child, map)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn parse_layer_data(data: &Element) -> Result<Vec<u32>, String> {
+    if data.child("chunk").is_some() {
+        return Err("chunked (infinite) layer data isn't supported".into());
+    }
+
+    match data.attr("encoding") {
+        None => {
+            data.children.iter().filter(|c| c.name == "tile").map(|t| t.attr_u32("gid")).collect()
+        },
+        Some("csv") => {
+            data.text.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| s.parse::<u32>().map_err(|e| format!("bad csv gid {:?} -> {:?}", s, e))).collect()
+        },
+        Some("base64") => {
+            let cleaned : String = data.text.chars().filter(|c| !c.is_whitespace()).collect();
+            let raw = base64::decode(&cleaned).map_err(|e| format!("bad base64 layer data -> {:?}", e))?;
+            let mut bytes = Vec::new();
+            match data.attr("compression") {
+                None => bytes = raw,
+                Some("zlib") => { ZlibDecoder::new(&raw[..]).read_to_end(&mut bytes).map_err(|e| format!("zlib -> {:?}", e))?; },
+                Some("gzip") => { GzDecoder::new(&raw[..]).read_to_end(&mut bytes).map_err(|e| format!("gzip -> {:?}", e))?; },
+                Some(other) => return Err(format!("unsupported layer compression {:?}", other)),
+            }
+            Ok(bytes.chunks(4).filter(|c| c.len() == 4).map(|c| (c[0] as u32) | ((c[1] as u32) << 8) | ((c[2] as u32) << 16) | ((c[3] as u32) << 24)).collect())
+        },
+        Some(other) => Err(format!("unsupported layer encoding {:?}", other)),
+    }
+}
+
+fn parse_tileset(element: &Element, first_gid: u32, dir: &Path) -> Result<Tileset, String> {
+    let image = match element.child("image") {
+        Some(image) => Some(TilesetImage {
+            source: resolve(dir, image.attr("source").ok_or("image without source")?),
+            width: image.attr_u32("width")?,
+            height: image.attr_u32("height")?,
+        }),
+        None => None,
+    };
+
+    let mut tile_properties = HashMap::default();
+    for tile in element.children.iter().filter(|c| c.name == "tile") {
+        let properties = parse_properties(tile)?;
+        if !properties.is_empty() {
+            tile_properties.insert(tile.attr_u32("id")?, properties);
+        }
+    }
+
+    Ok(Tileset {
+        first_gid: first_gid,
+        name: element.attr("name").unwrap_or("").to_string(),
+        source: None,
+        tile_width: element.attr_u32("tilewidth")?,
+        tile_height: element.attr_u32("tileheight")?,
+        spacing: element.attr_u32("spacing")?,
+        margin: element.attr_u32("margin")?,
+        columns: element.attr_u32("columns")?,
+        tile_count: element.attr_u32("tilecount")?,
+        image: image,
+        tile_properties: tile_properties,
+    })
+}
+
+fn parse_object(element: &Element) -> Result<TiledObject, String> {
+    let shape = if element.child("ellipse").is_some() {
+        ObjectShape::Ellipse
+    } else if element.child("point").is_some() {
+        ObjectShape::Point
+    } else if let Some(polygon) = element.child("polygon") {
+        ObjectShape::Polygon(parse_points(polygon.attr("points").unwrap_or(""))?)
+    } else if let Some(polyline) = element.child("polyline") {
+        ObjectShape::Polyline(parse_points(polyline.attr("points").unwrap_or(""))?)
+    } else {
+        ObjectShape::Rectangle
+    };
+
+    Ok(TiledObject {
+        id: element.attr_u32("id")?,
+        name: element.attr("name").unwrap_or("").to_string(),
+        kind: element.attr("type").or(element.attr("class")).unwrap_or("").to_string(),
+        x: element.attr_f64("x")?,
+        y: element.attr_f64("y")?,
+        width: element.attr_f64("width")?,
+        height: element.attr_f64("height")?,
+        rotation: element.attr_f64("rotation")?,
+        gid: element.attr("gid").map(|_| element.attr_u32("gid")).map_or(Ok(None), |r| r.map(Some))?,
+        visible: element.attr("visible") != Some("0"),
+        shape: shape,
+        properties: parse_properties(element)?,
+    })
+}
+
+// "0,0 16,0 16,16"
+fn parse_points(points: &str) -> Result<Vec<(f64, f64)>, String> {
+    points.split_whitespace().map(|pair| {
+        let mut parts = pair.split(',').map(|p| p.parse::<f64>());
+        match (parts.next(), parts.next()) {
+            (Some(Ok(x)), Some(Ok(y))) => Ok((x, y)),
+            _ => Err(format!("bad point {:?}", pair)),
+        }
+    }).collect()
+}
+
+fn parse_properties(element: &Element) -> Result<Properties, String> {
+    let mut properties = HashMap::default();
+    if let Some(list) = element.child("properties") {
+        for property in list.children.iter().filter(|c| c.name == "property") {
+            let name = property.attr("name").ok_or("property without a name")?.to_string();
+            // multi line strings are written as text content
+            let raw = property.attr("value").map(|s| s.to_string()).unwrap_or_else(|| property.text.clone());
+            let value = match property.attr("type").unwrap_or("string") {
+                "int" => PropertyValue::Int(raw.trim().parse::<i64>().map_err(|e| format!("property {:?} -> {:?}", name, e))?),
+                "float" => PropertyValue::Float(raw.trim().parse::<f64>().map_err(|e| format!("property {:?} -> {:?}", name, e))?),
+                "bool" => PropertyValue::Bool(raw.trim() == "true"),
+                "color" => PropertyValue::Color(raw),
+                "file" => PropertyValue::File(raw),
+                _ => PropertyValue::String(raw),
+            };
+            properties.insert(name, value);
+        }
+    }
+    Ok(properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP : &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.2" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <tileset firstgid="1" name="ground" tilewidth="16" tileheight="16" spacing="0" margin="0" tilecount="8" columns="4">
+  <image source="ground.png" width="64" height="32"/>
+ </tileset>
+ <layer id="1" name="floor" width="3" height="2">
+  <data encoding="csv">
+1,2,0,
+5,2147483650,3
+</data>
+ </layer>
+ <objectgroup id="2" name="spawns">
+  <object id="1" name="player" type="spawn" x="8" y="24">
+   <properties>
+    <property name="facing" value="north"/>
+    <property name="health" type="int" value="3"/>
+   </properties>
+   <point/>
+  </object>
+ </objectgroup>
+</map>"#;
+
+    #[test]
+    fn parses_layers_and_objects() {
+        let map = parse_tmx(Path::new("textures/level.tmx"), MAP.as_bytes()).unwrap();
+        assert_eq!(map.tilesets[0].image.as_ref().unwrap().source, PathBuf::from("textures/ground.png"));
+
+        let floor = map.tile_layer("floor").unwrap();
+        assert_eq!(floor.gids.len(), 6);
+        assert_eq!(floor.gid(0, 1), 5);
+
+        let player = &map.object_layer("spawns").unwrap().objects[0];
+        assert_eq!(player.shape, ObjectShape::Point);
+        assert_eq!(player.properties.get("health"), Some(&PropertyValue::Int(3)));
+        assert_eq!(player.properties.get("facing"), Some(&PropertyValue::String("north".into())));
+    }
+
+    #[test]
+    fn regions_offset_in_to_the_tileset_image() {
+        let map = parse_tmx(Path::new("textures/level.tmx"), MAP.as_bytes()).unwrap();
+        let mut images = NamedRegions::empty();
+        images.insert("ground".into(), TextureRegion { u_min: 0, u_max: 64, v_min: 0, v_max: 32, layer: 2, texture_width: 64, texture_height: 32 });
+
+        let root = Path::new("textures");
+        let second = map.region_for_gid(2, root, &images).unwrap().unwrap();
+        assert_eq!((second.u_min, second.u_max, second.v_min, second.v_max), (16, 32, 16, 32));
+
+        let below = map.region_for_gid(5, root, &images).unwrap().unwrap();
+        assert_eq!((below.v_min, below.v_max), (0, 16));
+
+        // flipped horizontally
+        let flipped = map.region_for_gid(2147483650, root, &images).unwrap().unwrap();
+        assert_eq!((flipped.u_min, flipped.u_max), (32, 16));
+        assert_eq!(map.region_for_gid(0, root, &images).unwrap(), None);
+    }
+
+    #[test]
+    fn fill_tilemap_checks_the_texture_layer() {
+        use Vec3;
+        use render::Blend;
+        use render::texture_region::TextureAtlas;
+
+        let map = parse_tmx(Path::new("textures/level.tmx"), MAP.as_bytes()).unwrap();
+        let mut images = NamedRegions::empty();
+        images.insert("ground".into(), TextureRegion { u_min: 0, u_max: 64, v_min: 0, v_max: 32, layer: 2, texture_width: 64, texture_height: 32 });
+        let root = Path::new("textures");
+        let floor = map.tile_layer("floor").unwrap();
+
+        let atlas = TextureAtlas { texture_width: 64, texture_height: 32, tile_size: 16 };
+        let mut tilemap = Tilemap::new(3, 2, 16, atlas, Vec3::new(1.0, 1.0, 1.0));
+        let right = tilemap.add_layer("right", 2, 0.0, Blend::None);
+        let wrong = tilemap.add_layer("wrong", 0, 0.0, Blend::None);
+
+        map.fill_tilemap(floor, &mut tilemap, right, root, &images).unwrap();
+        assert_eq!(tilemap.get(right, 1, 0), Some(Tile { u: 1, v: 1 }));
+        assert_eq!(tilemap.get(right, 1, 1), Some(Tile { u: 1, v: 1 })); // flipped, placed as is
+        assert_eq!(tilemap.get(right, 2, 0), None);
+
+        assert!(map.fill_tilemap(floor, &mut tilemap, wrong, root, &images).is_err());
+        assert!(map.fill_tilemap(floor, &mut tilemap, 7, root, &images).is_err());
+
+        let mut smaller = Tilemap::new(3, 2, 16, TextureAtlas { texture_width: 32, texture_height: 32, tile_size: 16 }, Vec3::new(1.0, 1.0, 1.0));
+        let layer = smaller.add_layer("floor", 2, 0.0, Blend::None);
+        assert!(map.fill_tilemap(floor, &mut smaller, layer, root, &images).is_err());
+    }
+
+    #[test]
+    fn tiles_outside_the_loaded_image_are_errors() {
+        let map = parse_tmx(Path::new("textures/level.tmx"), MAP.as_bytes()).unwrap();
+        let mut images = NamedRegions::empty();
+        // the tileset says 64x32, the loaded image is half that
+        images.insert("ground".into(), TextureRegion { u_min: 0, u_max: 32, v_min: 0, v_max: 32, layer: 0, texture_width: 64, texture_height: 32 });
+        let root = Path::new("textures");
+
+        assert!(map.region_for_gid(2, root, &images).unwrap().is_some());
+        assert!(map.region_for_gid(3, root, &images).is_err());
+    }
+}
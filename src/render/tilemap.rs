@@ -0,0 +1,365 @@
+use std::sync::Arc;
+
+use gfx_device_gl;
+use aphid::{HashMap, HashSet};
+
+use {JamResult, Vec3, Vec4, Camera, color};
+use render::{Vertex, Uniforms, Blend, GeometryTesselator, down_size_m4};
+use render::texture_region::TextureAtlas;
+use render::gfx::{GeometryBuffer, OpenGLRenderer};
+use render::parallel::tesselate_parallel;
+
+// a cell of the atlas
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Tile {
+    pub u: u32,
+    pub v: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileLayer {
+    pub name: String,
+    pub texture_layer: u32,
+    pub y: f64, // height of the floor this layer is drawn on
+    pub depth_adjust: f64,
+    pub blend: Blend,
+    pub visible: bool,
+    pub tiles: Vec<Option<Tile>>, // row major, x then z
+}
+
+type ChunkKey = (usize, u32, u32); // layer, chunk x, chunk z
+
+// floor tiles in the x/z plane, tile (0, 0) is anchored at the origin
+pub struct Tilemap {
+    pub width: u32, // in tiles along x
+    pub depth: u32, // in tiles along z
+    pub chunk_size: u32, // tiles along each side of a chunk
+    pub atlas: TextureAtlas,
+    pub scale: Vec3, // as GeometryTesselator
+    pub layers: Vec<TileLayer>,
+    pub dirty: HashSet<ChunkKey>,
+    pub chunks: HashMap<ChunkKey, GeometryBuffer<gfx_device_gl::Resources>>,
+}
+
+impl Tilemap {
+    pub fn new(width: u32, depth: u32, chunk_size: u32, atlas: TextureAtlas, scale: Vec3) -> Tilemap {
+        Tilemap {
+            width: width,
+            depth: depth,
+            chunk_size: chunk_size.max(1),
+            atlas: atlas,
+            scale: scale,
+            layers: Vec::new(),
+            dirty: HashSet::default(),
+            chunks: HashMap::default(),
+        }
+    }
+
+    // layers draw in the order they're added, returns the layer index
+    pub fn add_layer(&mut self, name: &str, texture_layer: u32, y: f64, blend: Blend) -> usize {
+        self.layers.push(TileLayer {
+            name: name.to_string(),
+            texture_layer: texture_layer,
+            y: y,
+            depth_adjust: 0.0,
+            blend: blend,
+            visible: true,
+            tiles: vec![None; (self.width * self.depth) as usize],
+        });
+        self.layers.len() - 1
+    }
+
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|l| l.name == name)
+    }
+
+    pub fn contains(&self, x: u32, z: u32) -> bool {
+        x < self.width && z < self.depth
+    }
+
+    pub fn get(&self, layer: usize, x: u32, z: u32) -> Option<Tile> {
+        if !self.contains(x, z) {
+            return None;
+        }
+        self.layers.get(layer).and_then(|l| l.tiles[(z * self.width + x) as usize])
+    }
+
+    // returns whether anything changed, only the chunk holding the tile is rebuilt
+    pub fn set(&mut self, layer: usize, x: u32, z: u32, tile: Option<Tile>) -> bool {
+        if !self.contains(x, z) || layer >= self.layers.len() {
+            return false;
+        }
+        let index = (z * self.width + x) as usize;
+        if self.layers[layer].tiles[index] == tile {
+            return false;
+        }
+        self.layers[layer].tiles[index] = tile;
+        let (cx, cz) = self.chunk_of(x, z);
+        self.dirty.insert((layer, cx, cz));
+        true
+    }
+
+    pub fn fill(&mut self, layer: usize, tile: Option<Tile>) {
+        for z in 0..self.depth {
+            for x in 0..self.width {
+                self.set(layer, x, z, tile);
+            }
+        }
+    }
+
+    // after changing the atlas, scale or a layer's settings
+    pub fn mark_all_dirty(&mut self) {
+        let (chunks_x, chunks_z) = self.chunk_counts();
+        for layer in 0..self.layers.len() {
+            for cz in 0..chunks_z {
+                for cx in 0..chunks_x {
+                    self.dirty.insert((layer, cx, cz));
+                }
+            }
+        }
+    }
+
+    pub fn chunk_of(&self, x: u32, z: u32) -> (u32, u32) {
+        (x / self.chunk_size, z / self.chunk_size)
+    }
+
+    pub fn chunk_counts(&self) -> (u32, u32) {
+        ((self.width + self.chunk_size - 1) / self.chunk_size, (self.depth + self.chunk_size - 1) / self.chunk_size)
+    }
+
+    pub fn tile_extent(&self) -> (f64, f64) {
+        tile_extent(&self.atlas, self.scale)
+    }
+
+    pub fn chunk_vertices(&self, layer: usize, cx: u32, cz: u32) -> Vec<Vertex> {
+        match self.layers.get(layer) {
+            Some(tile_layer) => layer_chunk_vertices(tile_layer, self.width, self.depth, self.chunk_size, &self.atlas, self.scale, cx, cz),
+            None => Vec::new(),
+        }
+    }
+
+    // inclusive chunk ranges ((min x, min z), (max x, max z)) a camera can see of the floor at y
+    pub fn visible_chunks(&self, camera: &Camera, y: f64) -> Option<((u32, u32), (u32, u32))> {
+        let (chunks_x, chunks_z) = self.chunk_counts();
+        if chunks_x == 0 || chunks_z == 0 {
+            return None;
+        }
+        let everything = Some(((0, 0), (chunks_x - 1, chunks_z - 1)));
+
+        let ivp = match camera.inverse_view_projection() {
+            Some(ivp) => ivp,
+            None => return everything,
+        };
+
+        let mut min = (::std::f64::MAX, ::std::f64::MAX);
+        let mut max = (::std::f64::MIN, ::std::f64::MIN);
+
+        for &(nx, ny) in &[(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let near = ivp * Vec4::new(nx, ny, -1.0, 1.0);
+            let far = ivp * Vec4::new(nx, ny, 1.0, 1.0);
+            let near = near.truncate() / near.w;
+            let far = far.truncate() / far.w;
+
+            // looking along the floor, it can't be bounded
+            if (far.y - near.y).abs() < 0.000001 {
+                return everything;
+            }
+            let t = (y - near.y) / (far.y - near.y);
+            let p = near + (far - near) * t;
+            min = (min.0.min(p.x), min.1.min(p.z));
+            max = (max.0.max(p.x), max.1.max(p.z));
+        }
+
+        let (tile_x, tile_z) = self.tile_extent();
+        let chunk_x = tile_x * self.chunk_size as f64;
+        let chunk_z = tile_z * self.chunk_size as f64;
+
+        if max.0 < 0.0 || max.1 < 0.0 || min.0 > chunk_x * chunks_x as f64 || min.1 > chunk_z * chunks_z as f64 {
+            return None;
+        }
+
+        let clamp_chunk = |v: f64, count: u32| -> i64 {
+            (v.floor() as i64).max(0).min(count as i64 - 1)
+        };
+
+        let min_cx = clamp_chunk(min.0 / chunk_x, chunks_x);
+        let max_cx = clamp_chunk(max.0 / chunk_x, chunks_x);
+        let min_cz = clamp_chunk(min.1 / chunk_z, chunks_z);
+        let max_cz = clamp_chunk(max.1 / chunk_z, chunks_z);
+
+        Some(((min_cx as u32, min_cz as u32), (max_cx as u32, max_cz as u32)))
+    }
+
+    // rebuilds dirty chunks in view then draws them, returns how many chunks were drawn
+    pub fn draw(&mut self, renderer: &mut OpenGLRenderer, camera: &Camera) -> JamResult<usize> {
+        let uniforms = Uniforms {
+            transform: down_size_m4(camera.view_projection().into()),
+            color: color::WHITE,
+        };
+
+        let mut drawn = 0;
+
+        for layer in 0..self.layers.len() {
+            if !self.layers[layer].visible {
+                continue;
+            }
+
+            let ((min_cx, min_cz), (max_cx, max_cz)) = match self.visible_chunks(camera, self.layers[layer].y) {
+                Some(range) => range,
+                None => continue,
+            };
+
+            for cz in min_cz..(max_cz + 1) {
+                for cx in min_cx..(max_cx + 1) {
+                    let key = (layer, cx, cz);
+
+                    // never built chunks are dirty too
+                    if self.dirty.remove(&key) || (!self.chunks.contains_key(&key) && self.chunk_has_tiles(layer, cx, cz)) {
+                        let vertices = self.chunk_vertices(layer, cx, cz);
+                        if vertices.is_empty() {
+                            self.chunks.remove(&key);
+                        } else {
+                            self.chunks.insert(key, renderer.upload(&vertices));
+                        }
+                    }
+
+                    if let Some(geometry) = self.chunks.get(&key) {
+                        renderer.draw(geometry, uniforms, self.layers[layer].blend)?;
+                        drawn += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(drawn)
+    }
+
+    // builds every dirty or never built chunk across worker threads, uploading in key order
+    // returns how many chunks were rebuilt, draw then only rebuilds what changes after this
+    pub fn rebuild_parallel(&mut self, renderer: &mut OpenGLRenderer, workers: usize) -> JamResult<usize> {
+        let (chunks_x, chunks_z) = self.chunk_counts();
+        let mut keys : Vec<ChunkKey> = Vec::new();
+        for layer in 0..self.layers.len() {
+            for cz in 0..chunks_z {
+                for cx in 0..chunks_x {
+                    let key = (layer, cx, cz);
+                    if self.dirty.contains(&key) || (!self.chunks.contains_key(&key) && self.chunk_has_tiles(layer, cx, cz)) {
+                        keys.push(key);
+                    }
+                }
+            }
+        }
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        // the workers need their own copy of the tiles, but only of layers with something to build
+        let layers : Vec<Option<TileLayer>> = self.layers.iter().enumerate().map(|(i, l)| {
+            if keys.iter().any(|&(layer, _, _)| layer == i) { Some(l.clone()) } else { None }
+        }).collect();
+        let layers = Arc::new(layers);
+        let (width, depth, chunk_size, atlas, scale) = (self.width, self.depth, self.chunk_size, self.atlas, self.scale);
+        let built = tesselate_parallel(keys.clone(), workers, move |&(layer, cx, cz)| {
+            match layers[layer] {
+                Some(ref tile_layer) => layer_chunk_vertices(tile_layer, width, depth, chunk_size, &atlas, scale, cx, cz),
+                None => Vec::new(),
+            }
+        })?;
+
+        for (key, vertices) in keys.iter().zip(built.into_iter()) {
+            self.dirty.remove(key);
+            if vertices.is_empty() {
+                self.chunks.remove(key);
+            } else {
+                self.chunks.insert(*key, renderer.upload(&vertices));
+            }
+        }
+
+        Ok(keys.len())
+    }
+
+    fn chunk_has_tiles(&self, layer: usize, cx: u32, cz: u32) -> bool {
+        let x_end = ((cx + 1) * self.chunk_size).min(self.width);
+        let z_end = ((cz + 1) * self.chunk_size).min(self.depth);
+        let tiles = &self.layers[layer].tiles;
+        ((cz * self.chunk_size)..z_end).any(|z| ((cx * self.chunk_size)..x_end).any(|x| tiles[(z * self.width + x) as usize].is_some()))
+    }
+}
+
+// world size of a tile along x and z, draw_floor_tile sizes depth by scale.y
+pub fn tile_extent(atlas: &TextureAtlas, scale: Vec3) -> (f64, f64) {
+    (atlas.tile_size as f64 * scale.x, atlas.tile_size as f64 * scale.y)
+}
+
+pub fn layer_chunk_vertices(tile_layer: &TileLayer, width: u32, depth: u32, chunk_size: u32, atlas: &TextureAtlas, scale: Vec3, cx: u32, cz: u32) -> Vec<Vertex> {
+    let mut vertices = Vec::new();
+    let tesselator = GeometryTesselator::new(scale);
+    let atlas_layer = atlas.layer(tile_layer.texture_layer);
+    let (tile_x, tile_z) = tile_extent(atlas, scale);
+
+    let x_end = ((cx + 1) * chunk_size).min(width);
+    let z_end = ((cz + 1) * chunk_size).min(depth);
+
+    for z in (cz * chunk_size)..z_end {
+        for x in (cx * chunk_size)..x_end {
+            if let Some(tile) = tile_layer.tiles[(z * width + x) as usize] {
+                let region = atlas_layer.at(tile.u, tile.v);
+                tesselator.draw_floor_tile(&mut vertices, &region, x as f64 * tile_x, tile_layer.y, z as f64 * tile_z, tile_layer.depth_adjust);
+            }
+        }
+    }
+
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> Tilemap {
+        let atlas = TextureAtlas { texture_width: 256, texture_height: 256, tile_size: 16 };
+        let mut map = Tilemap::new(40, 40, 16, atlas, Vec3::new(1.0 / 16.0, 1.0 / 16.0, 1.0 / 16.0));
+        map.add_layer("floor", 0, 0.0, Blend::None);
+        map
+    }
+
+    #[test]
+    fn only_the_touched_chunk_is_dirty() {
+        let mut map = map();
+        assert!(map.set(0, 17, 3, Some(Tile { u: 1, v: 2 })));
+        assert!(!map.set(0, 17, 3, Some(Tile { u: 1, v: 2 })));
+        assert_eq!(map.dirty.len(), 1);
+        assert!(map.dirty.contains(&(0, 1, 0)));
+        assert_eq!(map.chunk_counts(), (3, 3));
+    }
+
+    #[test]
+    fn chunk_vertices_cover_set_tiles() {
+        let mut map = map();
+        map.set(0, 0, 0, Some(Tile { u: 0, v: 0 }));
+        map.set(0, 15, 15, Some(Tile { u: 0, v: 0 }));
+        map.set(0, 16, 0, Some(Tile { u: 0, v: 0 }));
+        assert_eq!(map.chunk_vertices(0, 0, 0).len(), 12);
+        assert_eq!(map.chunk_vertices(0, 1, 0).len(), 6);
+    }
+
+    #[test]
+    fn visible_chunks_follow_the_camera() {
+        use std::f64::consts::FRAC_PI_2;
+        use cgmath::Rad;
+        use Dimensions;
+
+        let map = map();
+        // looking straight down, 20 units wide and 20 / sqrt(2) deep, tiles are a unit and chunks 16
+        let camera = |x: f64, z: f64| Camera {
+            at: Vec3::new(x, 0.0, z),
+            pitch: Rad(FRAC_PI_2),
+            viewport: Dimensions { pixels: (320, 320), points: (320, 320) },
+            points_per_unit: 16.0,
+        };
+
+        assert_eq!(map.visible_chunks(&camera(30.0, 30.0), 0.0), Some(((1, 1), (2, 2))));
+        assert_eq!(map.visible_chunks(&camera(4.0, 4.0), 0.0), Some(((0, 0), (0, 0))));
+        assert_eq!(map.visible_chunks(&camera(200.0, 200.0), 0.0), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::VecDeque;
+
+use cgmath::{Rad, InnerSpace, Angle};
+use aphid::Seconds;
+
+use {Vec3};
+use color::ColorFloatRaw;
+use render::Vertex;
+use render::quads::GeometryTesselator;
+use render::texture_region::TextureRegion;
+
+// the way an orthographic camera with the given pitch looks, in world space
+pub fn view_direction(pitch: Rad<f64>) -> Vec3 {
+    Vec3::new(0.0, -pitch.sin(), -pitch.cos())
+}
+
+// start is the first point given, usually the head of a trail
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RibbonStyle {
+    pub start_width: f64, // world units
+    pub end_width: f64,
+    pub start_color: ColorFloatRaw,
+    pub end_color: ColorFloatRaw,
+}
+
+impl RibbonStyle {
+    // same width and colour all the way along
+    pub fn uniform(width: f64, color: ColorFloatRaw) -> RibbonStyle {
+        RibbonStyle {
+            start_width: width,
+            end_width: width,
+            start_color: color,
+            end_color: color,
+        }
+    }
+
+    // narrows to a point and fades out towards the end
+    pub fn tapered(width: f64, color: ColorFloatRaw) -> RibbonStyle {
+        RibbonStyle {
+            start_width: width,
+            end_width: 0.0,
+            start_color: color,
+            end_color: [color[0], color[1], color[2], 0.0],
+        }
+    }
+
+    pub fn width_at(&self, s: f64) -> f64 {
+        self.start_width + (self.end_width - self.start_width) * s
+    }
+
+    pub fn color_at(&self, s: f64) -> ColorFloatRaw {
+        let s = s as f32;
+        let (a, b) = (self.start_color, self.end_color);
+        [a[0] + (b[0] - a[0]) * s, a[1] + (b[1] - a[1]) * s, a[2] + (b[2] - a[2]) * s, a[3] + (b[3] - a[3]) * s]
+    }
+}
+
+impl GeometryTesselator {
+    // a strip through the points, spread across the view so it faces the camera
+    // u runs from u_min at the first point to u_max at the last by distance, v across the width
+    pub fn draw_ribbon(&self, vertices: &mut Vec<Vertex>, tr: &TextureRegion, points: &[Vec3], style: &RibbonStyle, pitch: Rad<f64>) {
+        if points.len() < 2 {
+            return;
+        }
+
+        let layer_f = tr.layer as f32;
+        let view = view_direction(pitch);
+        let facing = view * -1.0;
+        let normal = [facing.x as f32, facing.y as f32, facing.z as f32];
+
+        let mut distances = Vec::with_capacity(points.len());
+        let mut total = 0.0;
+        distances.push(0.0);
+        for pair in points.windows(2) {
+            total += (pair[1] - pair[0]).magnitude();
+            distances.push(total);
+        }
+        if total <= 0.0 {
+            return;
+        }
+
+        // the two edge vertices at each point
+        let mut edges : Vec<(Vertex, Vertex)> = Vec::with_capacity(points.len());
+        let mut last_side = Vec3::new(1.0, 0.0, 0.0);
+        for (i, &point) in points.iter().enumerate() {
+            let previous = points[if i == 0 { 0 } else { i - 1 }];
+            let next = points[(i + 1).min(points.len() - 1)];
+            let tangent = next - previous;
+
+            // a tangent along the view has no screen direction, keep the last one
+            let side = tangent.cross(view);
+            let side = if side.magnitude2() > 0.0000001 { side.normalize() } else { last_side };
+            last_side = side;
+
+            let s = distances[i] / total;
+            let half = side * (style.width_at(s) / 2.0);
+            let color = style.color_at(s);
+            let u = tr.nu_min() + (s as f32) * (tr.nu_max() - tr.nu_min());
+
+            let left = point - half;
+            let right = point + half;
+            edges.push((
+                Vertex { position: [left.x as f32, left.y as f32, left.z as f32], tex_coord: [u, tr.nv_min(), layer_f], color: color, normal: normal },
+                Vertex { position: [right.x as f32, right.y as f32, right.z as f32], tex_coord: [u, tr.nv_max(), layer_f], color: color, normal: normal },
+            ));
+        }
+
+        for pair in edges.windows(2) {
+            self.emit_quad(vertices, [pair[0].0, pair[1].0, pair[1].1, pair[0].1]);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrailPoint {
+    pub position: Vec3,
+    pub age: Seconds,
+}
+
+// a history of positions, newest first, that ages out behind whatever's moving
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trail {
+    pub points: VecDeque<TrailPoint>,
+    pub max_points: usize,
+    pub lifetime: Seconds, // points older than this are dropped
+    pub min_distance: f64, // closer moves update the head instead of adding a point
+}
+
+impl Trail {
+    pub fn new(max_points: usize, lifetime: Seconds) -> Trail {
+        Trail {
+            points: VecDeque::new(),
+            max_points: max_points.max(2),
+            lifetime: lifetime,
+            min_distance: 0.0,
+        }
+    }
+
+    pub fn with_min_distance(self, min_distance: f64) -> Trail {
+        Trail {
+            min_distance: min_distance,
+            .. self
+        }
+    }
+
+    pub fn push(&mut self, position: Vec3) {
+        // the head follows the object until it's min_distance from the point behind, then it stays put
+        let merge = match (self.points.get(0), self.points.get(1)) {
+            (Some(head), Some(behind)) => (head.position - behind.position).magnitude() < self.min_distance,
+            _ => false,
+        };
+        if merge {
+            self.points[0] = TrailPoint { position: position, age: 0.0 };
+        } else {
+            self.points.push_front(TrailPoint { position: position, age: 0.0 });
+            self.points.truncate(self.max_points);
+        }
+    }
+
+    pub fn update(&mut self, dt: Seconds) {
+        for p in self.points.iter_mut() {
+            p.age += dt;
+        }
+        while self.points.back().map(|p| p.age > self.lifetime).unwrap_or(false) {
+            self.points.pop_back();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    pub fn positions(&self) -> Vec<Vec3> {
+        self.points.iter().map(|p| p.position).collect()
+    }
+
+    // head to tail, so the style's start is at the head
+    pub fn draw(&self, vertices: &mut Vec<Vertex>, tesselator: &GeometryTesselator, tr: &TextureRegion, style: &RibbonStyle, pitch: Rad<f64>) {
+        tesselator.draw_ribbon(vertices, tr, &self.positions(), style, pitch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ribbon_tapers_and_runs_uvs_along_its_length() {
+        let tr = TextureRegion { u_min: 0, u_max: 64, v_min: 0, v_max: 16, layer: 1, texture_width: 64, texture_height: 64 };
+        let t = GeometryTesselator::new(Vec3::new(1.0, 1.0, 1.0));
+        let points = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(3.0, 0.0, 0.0)];
+        let style = RibbonStyle::tapered(1.0, [1.0, 1.0, 1.0, 1.0]);
+
+        let mut vertices = Vec::new();
+        t.draw_ribbon(&mut vertices, &tr, &points, &style, Rad(0.0));
+        assert_eq!(vertices.len(), 12);
+
+        // first quad: start left, middle left, middle right, start right
+        assert_eq!(vertices[0].position, [0.0, -0.5, 0.0]);
+        assert_eq!(vertices[0].tex_coord[0], 0.0);
+        assert!((vertices[1].tex_coord[0] - 1.0 / 3.0).abs() < 0.0001);
+        assert_eq!(vertices[1].color[3], 1.0 - 1.0 / 3.0);
+
+        // the end narrows to nothing
+        assert_eq!(vertices[7].position, [3.0, 0.0, 0.0]);
+        assert_eq!(vertices[8].position, [3.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn trail_drops_old_and_close_points() {
+        let mut trail = Trail::new(8, 0.5).with_min_distance(0.5);
+        trail.push(Vec3::new(0.0, 0.0, 0.0));
+        trail.push(Vec3::new(1.0, 0.0, 0.0));
+        trail.push(Vec3::new(1.2, 0.0, 0.0));
+        trail.push(Vec3::new(1.3, 0.0, 0.0));
+        assert_eq!(trail.positions(), vec![Vec3::new(1.3, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0)]);
+
+        trail.update(0.4);
+        trail.push(Vec3::new(2.0, 0.0, 0.0));
+        trail.update(0.2);
+        assert_eq!(trail.positions(), vec![Vec3::new(2.0, 0.0, 0.0)]);
+    }
+}
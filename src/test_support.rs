@@ -0,0 +1,37 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// a scratch directory for tests that touch files, unique per test run and removed on drop
+pub struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    // name keeps tests in the same run apart, the process id keeps concurrent runs apart
+    pub fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("jam_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path: path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // (over)writes a file relative to the directory, returning its full path
+    pub fn write(&self, name: &str, bytes: &[u8]) -> PathBuf {
+        let path = self.path.join(name);
+        File::create(&path).unwrap().write_all(bytes).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
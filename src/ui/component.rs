@@ -19,6 +19,7 @@ pub fn button<Ev, F>(frame: Rect<i32>,
         on_event: Some(Box::new(nf)),
         layers: Vec::new(),
         sub_views : Vec::new(),
+        clips: false,
     }
 }
 
@@ -2,12 +2,11 @@
 use aphid::{HashSet, HashMap, Seconds};
 
 use FontDirectory;
-use {Camera, Vec3, Vec2, InputState, JamResult, rgb};
+use {Camera, Vec3, Vec2, InputState, JamResult, rgb, App, AppConfig, AppState};
 use color;
 use render::*;
 use render::gfx::{Renderer,OpenGLRenderer, GeometryBuffer, construct_opengl_renderer};
 
-use time;
 use cgmath::Rad;
 
 use std::f64::consts::PI;
@@ -21,12 +20,8 @@ pub fn run() {
     let texture_dir = TextureDirectory::for_path("resources/textures", hashset!["png".into()]);
     let font_dir = FontDirectory::for_path("resources/fonts");
 
-    let file_resources = FileResources {
-        resources: resources_path,
-        shader_pair: shader_pair,
-        texture_directory: texture_dir,
-        font_directory: font_dir,
-    };
+    let file_resources = FileResources::new(resources_path, shader_pair, texture_dir, font_dir)
+        .with_compact_shader_pair(ShaderPair::for_paths("resources/shader/compact.vert", "resources/shader/fat.frag"));
 
     let renderer = construct_opengl_renderer(file_resources, (800, 600), true, "ui example").expect("a renderer");
 
@@ -35,7 +30,7 @@ pub fn run() {
         points: (800,600),
     };
 
-    let mut app = App {
+    let example = Example {
         name: "mixalot".into(),
         camera: Camera {
             at: Vec3::new(0.0, 0.0, 0.0),
@@ -46,45 +41,28 @@ pub fn run() {
         zoom: 1.0,
         points_per_unit: 16.0,
         n: 0, // frame counter
-        renderer: renderer,
         widget_runner: WidgetRunner::new(ExampleWidget {}, ExampleState::sample(), dimensions),
     };
-    app.run()
+
+    let config = AppConfig {
+        clear_color: rgb(210, 228, 237),
+        .. AppConfig::default()
+    };
+
+    App::new(example, renderer, config).and_then(|mut app| app.run()).expect("no errors")
 }
 
 
-struct App {
+struct Example {
     name : String,
     camera : Camera,
     zoom : f64,
     points_per_unit : f64,
     n : u64,
-    renderer : OpenGLRenderer,
     widget_runner: WidgetRunner<ExampleWidget>,
 }
 
-impl App {
-    fn run(&mut self) {
-        let mut last_time = time::precise_time_ns();
-        'main: loop {
-            let (dimensions, input_state) = self.renderer.begin_frame(rgb(210, 228, 237));
-
-            let time = time::precise_time_ns();
-            let delta_time = ((time - last_time) as f64) / 1_000_000.0;
-
-            self.update(&input_state, dimensions, delta_time);
-
-            let res = self.render();
-
-            self.renderer.finish_frame().expect("no errors");
-
-            last_time = time;
-            if input_state.close {
-                break;
-            }
-        }
-    }
-
+impl Example {
     fn units_per_point(&self) -> f64 {
         1.0 / self.points_per_unit
     }
@@ -94,9 +72,10 @@ impl App {
         let tesselator_scale = Vec3::new(upp, upp, upp);
         GeometryTesselator::new(tesselator_scale)
     }
+}
 
-    #[allow(unused_variables)]
-    fn update(&mut self, input_state:&InputState, dimensions:Dimensions, delta_time: Seconds) {
+impl AppState for Example {
+    fn input(&mut self, input_state:&InputState, dimensions:Dimensions) {
         let mut external_events = Vec::new();
 
         if input_state.mouse.left_released() {
@@ -106,12 +85,12 @@ impl App {
         self.widget_runner.run(input_state.clone(), external_events, dimensions);
     }
 
-    fn render(&mut self) -> JamResult<()> {
-        let mut t = self.tesselator();
-
-        self.renderer.draw_view(&self.widget_runner.view());
+    fn update(&mut self, _dt: Seconds) {
+        self.n += 1;
+    }
 
-        Ok(())
+    fn render(&mut self, renderer: &mut OpenGLRenderer, _alpha: f64) -> JamResult<()> {
+        renderer.draw_view(self.widget_runner.view())
     }
 }
 
@@ -82,6 +82,16 @@ impl<F> Rect<F> where F: BaseNum {
         }
     }
 
+    // empty (zero sized) if they don't overlap
+    pub fn intersection(&self, other:&Rect<F>) -> Rect<F> {
+        let min = vec2(partial_max(self.min.x, other.min.x), partial_max(self.min.y, other.min.y));
+        let max = vec2(partial_min(self.max.x, other.max.x), partial_min(self.max.y, other.max.y));
+        Rect {
+            min: min,
+            max: vec2(partial_max(min.x, max.x), partial_max(min.y, max.y)),
+        }
+    }
+
     pub fn with_size(size:Vector2<F>) -> Rect<F> {
         Rect {
            min: Vector2::new(F::zero(), F::zero()),
@@ -90,6 +100,14 @@ impl<F> Rect<F> where F: BaseNum {
     }
 }
 
+fn partial_max<F : PartialOrd>(a:F, b:F) -> F {
+    if a > b { a } else { b }
+}
+
+fn partial_min<F : PartialOrd>(a:F, b:F) -> F {
+    if a < b { a } else { b }
+}
+
 // could be "widget behaviour"
 // widget allows &self just for immutable config ... might be a bad idea
 pub trait Widget {
@@ -107,4 +125,24 @@ pub trait Rasterable {
     fn raster(&self, image: &mut Bitmap, target: Rect<i32>);
 }
 
-// events .... mouse down, mouse up, move over (seems reasonable)
\ No newline at end of file
+// events .... mouse down, mouse up, move over (seems reasonable)
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intersection_of_overlapping_disjoint_and_contained_rects() {
+        let a = RectI::new(vec2(0, 0), vec2(10, 10));
+
+        let overlapping = RectI::new(vec2(5, -5), vec2(10, 10));
+        assert_eq!(a.intersection(&overlapping), Rect { min: vec2(5, 0), max: vec2(10, 5) });
+
+        let disjoint = RectI::new(vec2(20, 20), vec2(5, 5));
+        assert_eq!(a.intersection(&disjoint).size(), vec2(0, 0));
+
+        let contained = RectI::new(vec2(2, 3), vec2(4, 4));
+        assert_eq!(a.intersection(&contained), contained);
+        assert_eq!(contained.intersection(&a), contained);
+    }
+}
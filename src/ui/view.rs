@@ -12,11 +12,12 @@ pub struct View<Ev> {
     pub on_event: Option<Box<Fn(MouseEvent) -> Option<Ev>>>,
     pub layers: Vec<Layer>,
     pub sub_views : Vec<View<Ev>>,
+    pub clips: bool, // layers and sub views are scissored to this view's frame
 }
 
 impl<Ev> fmt::Debug for View<Ev> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "View {{ frame: {:?}, layers: {:?} sub_view: {:?} }}", self.frame, self.layers.len(), self.sub_views.len())
+        write!(f, "View {{ frame: {:?}, layers: {:?} sub_view: {:?} clips: {:?} }}", self.frame, self.layers.len(), self.sub_views.len(), self.clips)
     }
 }
 
@@ -30,7 +31,7 @@ impl<Ev> View<Ev> {
     }
 
     pub fn layer_iter<'a>(&'a self) -> LayerIterator<'a, Ev> {
-        let queue = vec![(self, vec2(0, 0), 0)];
+        let queue = vec![(self, vec2(0, 0), 0, None)];
         LayerIterator {
             views: queue,
             layer_idx: 0,
@@ -58,21 +59,32 @@ impl<'a, Ev> Iterator for ViewIterator<'a, Ev> {
 }
 
 pub struct LayerIterator<'a, Ev> where Ev: 'a {
-    views : Vec<(&'a View<Ev>, Point2I, ZLayer)>,
+    views : Vec<(&'a View<Ev>, Point2I, ZLayer, Option<RectI>)>, // last is the clip inherited from parents
     layer_idx: usize,
 }
 
+// the clip in absolute coordinates that applies to a view's own layers and sub views
+fn effective_clip<Ev>(view: &View<Ev>, parent_origin: Point2I, inherited: Option<RectI>) -> Option<RectI> {
+    if view.clips {
+        let frame = view.frame.offset(parent_origin);
+        Some(inherited.map(|c| c.intersection(&frame)).unwrap_or(frame))
+    } else {
+        inherited
+    }
+}
+
 impl<'a, Ev> Iterator for LayerIterator<'a, Ev> {
-    type Item = (&'a Layer, RectI, (ZLayer, ZLayer));
+    type Item = (&'a Layer, RectI, (ZLayer, ZLayer), Option<RectI>);
 
-    fn next(&mut self) -> Option<(&'a Layer, RectI, (ZLayer, ZLayer))> {
-        if let Some(&(view, parent_origin, z)) = self.views.last() {
+    fn next(&mut self) -> Option<(&'a Layer, RectI, (ZLayer, ZLayer), Option<RectI>)> {
+        if let Some(&(view, parent_origin, z, inherited_clip)) = self.views.last() {
             if self.layer_idx < view.layers.len() {
                 let l = &view.layers[self.layer_idx];
                 let layer_z = self.layer_idx as ZLayer;
                 self.layer_idx += 1;
                 let layer_frame = l.frame.offset(parent_origin + view.frame.min);
-                return Some((l, layer_frame, (z, layer_z)));
+                let clip = effective_clip(view, parent_origin, inherited_clip);
+                return Some((l, layer_frame, (z, layer_z), clip));
             } else {
                 // reset layer for next view
                 self.layer_idx = 0;
@@ -80,9 +92,10 @@ impl<'a, Ev> Iterator for LayerIterator<'a, Ev> {
         }
 
         // no more layers
-        if let Some((view, parent_origin, z)) = self.views.pop() {
+        if let Some((view, parent_origin, z, inherited_clip)) = self.views.pop() {
+            let clip = effective_clip(view, parent_origin, inherited_clip);
             for sv in &view.sub_views {
-                self.views.push((sv, parent_origin + view.frame.min, z+1));
+                self.views.push((sv, parent_origin + view.frame.min, z+1, clip));
             }
             self.next()
         } else {
@@ -97,6 +110,7 @@ pub fn empty_view<Ev>(frame:RectI) -> View<Ev> {
         on_event: None,
         layers : Vec::new(),
         sub_views: Vec::new(),
+        clips: false,
     }
 }
 
@@ -136,6 +150,7 @@ pub fn label_view<Ev>(frame:RectI, text:String, text_color:Color, background_col
         on_event: None,
         layers : layers,
         sub_views: Vec::new(),
+        clips: false,
     }
 }
 
@@ -153,6 +168,7 @@ mod tests {
             on_event: None,
             layers : Vec::new(),
             sub_views: Vec::new(),
+            clips: false,
         }
     }
 
@@ -178,6 +194,7 @@ mod tests {
             on_event: None,
             layers : layers,
             sub_views: Vec::new(),
+            clips: false,
         }
     }
 
@@ -207,10 +224,35 @@ mod tests {
             v.sub_views.push(view_with_text(vec2(100 * i, 0)));
         }
 
-        println!("====== LAYERS =======");
+        let layers : Vec<_> = v.layer_iter().collect();
+        assert_eq!(layers.len(), 9);
+        assert!(layers.iter().all(|&(_, _, (z, _), clip)| z == 1 && clip.is_none()));
+    }
 
-        for (l, r, z) in v.layer_iter() {
-            println!("layer -> {:?} rect -> {:?} z -> {:?}", l, r, z);
+    #[test]
+    fn clips_are_inherited_and_narrowed() {
+        let mut root = empty_view(vec2(100, 100));
+        root.clips = true;
+        let mut child = view_with_text(vec2(5, 0));
+        let mut grandchild = view_with_text(vec2(0, 5));
+        grandchild.clips = true;
+        child.sub_views.push(grandchild);
+        root.sub_views.push(child);
+
+        let root_clip = Rect { min: vec2(100, 100), max: vec2(110, 110) };
+        let narrowed = Rect { min: vec2(105, 105), max: vec2(110, 110) };
+
+        let layers : Vec<_> = root.layer_iter().collect();
+        assert_eq!(layers.len(), 6);
+        for &(_, frame, (z, _), clip) in &layers {
+            match z {
+                1 => {
+                    assert_eq!(frame.min.x, 105);
+                    assert_eq!(clip, Some(root_clip));
+                },
+                2 => assert_eq!(clip, Some(narrowed)),
+                _ => panic!("unexpected z {}", z),
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,170 @@
+use {Mat4, Vec3, Camera, Dimensions};
+use geometry::LineSegment;
+use ui::RectI;
+use cgmath::vec2;
+
+// a sub rectangle of the window with its own camera, for split screen
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Viewport {
+    pub rect: RectI, // pixels, origin bottom left of the window (same as scissor/clip rects)
+    pub camera: Camera,
+}
+
+impl Viewport {
+    // the camera's viewport is replaced with the dimensions of the sub rectangle, so projection aspect is correct
+    pub fn new(rect: RectI, camera: Camera, window: Dimensions) -> Viewport {
+        let mut camera = camera;
+        camera.viewport = window.sub_dimensions(rect);
+        Viewport {
+            rect: rect,
+            camera: camera,
+        }
+    }
+
+    // call when the window is resized, with the rect recalculated for the new size
+    pub fn resize(&mut self, rect: RectI, window: Dimensions) {
+        self.rect = rect;
+        self.camera.viewport = window.sub_dimensions(rect);
+    }
+
+    // mouse positions are window pixels, origin top left (as in InputState)
+    pub fn contains_mouse(&self, window: Dimensions, x: i32, y: i32) -> bool {
+        let (lx, ly) = self.local_mouse_position(window, x, y);
+        let size = self.rect.size();
+        lx >= 0 && ly >= 0 && lx < size.x && ly < size.y
+    }
+
+    // mouse position relative to the top left of this viewport
+    pub fn local_mouse_position(&self, window: Dimensions, x: i32, y: i32) -> (i32, i32) {
+        let (_, window_height) = window.pixels;
+        let top = window_height as i32 - self.rect.max.y;
+        (x - self.rect.min.x, y - top)
+    }
+
+    pub fn world_line_segment_for_mouse_position(&self, window: Dimensions, x: i32, y: i32) -> Option<LineSegment> {
+        if self.contains_mouse(window, x, y) {
+            let (lx, ly) = self.local_mouse_position(window, x, y);
+            self.camera.world_line_segment_for_mouse_position(lx, ly)
+        } else {
+            None
+        }
+    }
+
+    pub fn ui_line_segment_for_mouse_position(&self, window: Dimensions, x: i32, y: i32) -> Option<(f64, f64)> {
+        if self.contains_mouse(window, x, y) {
+            let (lx, ly) = self.local_mouse_position(window, x, y);
+            self.camera.ui_line_segment_for_mouse_position(lx, ly)
+        } else {
+            None
+        }
+    }
+}
+
+// maps normalized device coordinates for the whole window in to the given sub rectangle
+pub fn viewport_transform(rect: RectI, window_pixels: (u32, u32)) -> Mat4 {
+    let (width, height) = window_pixels;
+    let w = width as f64;
+    let h = height as f64;
+    let size = rect.size();
+
+    let sx = (size.x as f64) / w;
+    let sy = (size.y as f64) / h;
+    let tx = (2.0 * (rect.min.x as f64) + (size.x as f64)) / w - 1.0;
+    let ty = (2.0 * (rect.min.y as f64) + (size.y as f64)) / h - 1.0;
+
+    Mat4::from_translation(Vec3::new(tx, ty, 0.0)) * Mat4::from_nonuniform_scale(sx, sy, 1.0)
+}
+
+// which viewport the mouse is over, and the world ray through it
+pub fn pick_viewport(viewports: &[Viewport], window: Dimensions, x: i32, y: i32) -> Option<(usize, LineSegment)> {
+    for (i, viewport) in viewports.iter().enumerate() {
+        if let Some(line) = viewport.world_line_segment_for_mouse_position(window, x, y) {
+            return Some((i, line));
+        }
+    }
+    None
+}
+
+// n side by side columns
+pub fn split_columns(window: Dimensions, n: u32) -> Vec<RectI> {
+    split_grid(window, n, 1)
+}
+
+// n stacked rows, first is the top
+pub fn split_rows(window: Dimensions, n: u32) -> Vec<RectI> {
+    split_grid(window, 1, n)
+}
+
+// row major from the top left, any left over pixels go to the last row/column
+pub fn split_grid(window: Dimensions, columns: u32, rows: u32) -> Vec<RectI> {
+    if columns == 0 || rows == 0 {
+        return Vec::new();
+    }
+
+    let (width, height) = window.pixels;
+    let cell_width = (width / columns) as i32;
+    let cell_height = (height / rows) as i32;
+
+    let mut rects = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let min_x = column as i32 * cell_width;
+            let max_x = if column == columns - 1 { width as i32 } else { min_x + cell_width };
+
+            // rows count down from the top, but rects are bottom left origin
+            let max_y = height as i32 - row as i32 * cell_height;
+            let min_y = if row == rows - 1 { 0 } else { max_y - cell_height };
+
+            rects.push(RectI {
+                min: vec2(min_x, min_y),
+                max: vec2(max_x, max_y),
+            });
+        }
+    }
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Rad, vec4};
+
+    fn window() -> Dimensions {
+        Dimensions { pixels: (800, 600), points: (800, 600) }
+    }
+
+    #[test]
+    fn grid_splits_from_the_top_left() {
+        let rects = split_grid(window(), 2, 2);
+        assert_eq!(rects.len(), 4);
+        assert_eq!(rects[0], RectI { min: vec2(0, 300), max: vec2(400, 600) });
+        assert_eq!(rects[3], RectI { min: vec2(400, 0), max: vec2(800, 300) });
+
+        // left over pixels go to the last column
+        let thirds = split_columns(window(), 3);
+        assert_eq!(thirds[2], RectI { min: vec2(532, 0), max: vec2(800, 600) });
+
+        assert!(split_grid(window(), 0, 2).is_empty());
+        assert!(split_rows(window(), 0).is_empty());
+    }
+
+    #[test]
+    fn transform_maps_the_window_in_to_the_rect() {
+        let m = viewport_transform(RectI { min: vec2(400, 0), max: vec2(800, 300) }, (800, 600));
+        let bottom_left = m * vec4(-1.0, -1.0, 0.0, 1.0);
+        let top_right = m * vec4(1.0, 1.0, 0.0, 1.0);
+        assert_eq!((bottom_left.x, bottom_left.y), (0.0, -1.0));
+        assert_eq!((top_right.x, top_right.y), (1.0, 0.0));
+    }
+
+    #[test]
+    fn mouse_is_local_to_the_viewport() {
+        let camera = Camera { at: Vec3::new(0.0, 0.0, 0.0), pitch: Rad(0.0), viewport: window(), points_per_unit: 16.0 };
+        let viewport = Viewport::new(RectI { min: vec2(400, 0), max: vec2(800, 300) }, camera, window());
+
+        assert_eq!(viewport.local_mouse_position(window(), 450, 350), (50, 50));
+        assert!(viewport.contains_mouse(window(), 450, 350));
+        assert!(!viewport.contains_mouse(window(), 450, 100));
+        assert!(!viewport.contains_mouse(window(), 350, 350));
+    }
+}